@@ -0,0 +1,57 @@
+use clap::Parser;
+
+use viterbi::prelude::*;
+use viterbi::simulate::{to_csv, Simulation};
+
+/// print the bit and frame error rates of the CCSDS code against Eb/N0 as CSV
+#[derive(Parser)]
+#[command()]
+struct Cli {
+	/// the lowest Eb/N0 to simulate, in dB
+	#[arg(long, default_value_t = 0.0)]
+	from: f64,
+
+	/// the highest Eb/N0 to simulate, in dB
+	#[arg(long, default_value_t = 5.0)]
+	to: f64,
+
+	/// the step between each Eb/N0, in dB
+	#[arg(long, default_value_t = 0.5)]
+	step: f64,
+
+	/// how many bits are in each frame
+	#[arg(short, long, default_value_t = 1024)]
+	frame_len: usize,
+
+	/// how many bit errors to wait for at each Eb/N0
+	#[arg(short, long, default_value_t = 100)]
+	errors: u64,
+
+	/// use the register exchange decoder instead of the traceback one
+	#[arg(short, long)]
+	register: bool,
+
+	/// make hard decisions on the received symbols
+	#[arg(long)]
+	hard: bool,
+}
+
+fn main() {
+	let cli = Cli::parse();
+
+	let steps = ((cli.to - cli.from) / cli.step).round() as usize;
+	let eb_n0s: Vec<f64> = (0..=steps).map(|i| cli.from + i as f64 * cli.step).collect();
+
+	let mut simulation = Simulation::new(&Code::ccsds(), cli.frame_len).with_target_errors(cli.errors);
+	if cli.hard {
+		simulation = simulation.with_hard_decisions();
+	}
+
+	let points = if cli.register {
+		simulation.sweep(&eb_n0s, |code, _| RegisterDecoderState::with_code(code))
+	} else {
+		simulation.sweep(&eb_n0s, DecoderState::with_code)
+	};
+
+	print!("{}", to_csv(&points));
+}
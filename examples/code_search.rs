@@ -0,0 +1,40 @@
+use clap::Parser;
+
+use viterbi::analysis::search_codes;
+
+/// search for the best rate 1/n convolutional codes of a constraint length, printing their generators in octal
+#[derive(Parser)]
+#[command()]
+struct Cli {
+	/// the constraint length (K)
+	constraint_len: u8,
+
+	/// the number of outputs (n in rate 1/n)
+	outputs: usize,
+
+	/// how many codes to print
+	#[arg(short, long, default_value_t = 10)]
+	count: usize,
+
+	/// how many terms of the distance spectrum to rank the codes by
+	#[arg(short, long, default_value_t = 5)]
+	terms: usize,
+}
+
+fn main() {
+	let cli = Cli::parse();
+
+	for result in search_codes(cli.constraint_len, cli.outputs, cli.terms, cli.count) {
+		let generators: Vec<String> = result.code.generators().iter()
+			.map(|g| format!("{g:o}"))
+			.collect();
+
+		println!(
+			"({}) d_free = {}, A_d = {:?}, B_d = {:?}",
+			generators.join(", "),
+			result.spectrum.free_distance,
+			result.spectrum.paths,
+			result.spectrum.input_weights,
+		);
+	}
+}
@@ -6,6 +6,7 @@ use bytes::Bytes;
 use bytesize::ByteSize;
 use rand::prelude::*;
 
+use viterbi::channel::{BinarySymmetric, Channel};
 use viterbi::prelude::*;
 
 #[derive(Parser)]
@@ -20,7 +21,16 @@ struct Cli {
 
 	/// how many bytes should each encoder be fed
 	#[arg(short, default_value_t = 127)]
-	packet_size: usize
+	packet_size: usize,
+
+	/// decode the packets in parallel using `decode_batch`
+	#[arg(short, long)]
+	batch: bool,
+
+	/// send the encoded data through a binary symmetric channel that flips bits with this probability,
+	/// and count the errors left after decoding instead of expecting none
+	#[arg(long)]
+	bsc: Option<f64>,
 }
 
 fn main() {
@@ -50,13 +60,27 @@ fn main() {
 	let encoding_time = encoding_timer.elapsed();
 	dbg!(encoding_time.as_millis());
 
+	let transmitted = match cli.bsc {
+		Some(p) => BinarySymmetric::new(p, 0).transmit(&transmitted).into(),
+		None => transmitted,
+	};
+
 	println!("decoding is starting");
 	let decoding_timer = Instant::now();
-	let output = black_box(decode(transmitted, cli.packet_size));
+	let output = if cli.batch {
+		black_box(decode_batch(&transmitted, cli.packet_size).into())
+	} else {
+		black_box(decode(transmitted, cli.packet_size))
+	};
 	let decoding_time = decoding_timer.elapsed();
 	dbg!(decoding_time.as_millis());
 
-	assert_eq!(data, output);
+	if cli.bsc.is_some() {
+		let errors: u32 = data.iter().zip(output.iter()).map(|(a, b)| (a ^ b).count_ones()).sum();
+		println!("bit errors after decoding: {errors} ({:e})", errors as f64 / (data.len() * 8) as f64);
+	} else {
+		assert_eq!(data, output);
+	}
 }
 
 fn random_bytes(len: usize) -> Bytes {
@@ -68,30 +92,33 @@ fn random_bytes(len: usize) -> Bytes {
 }
 
 fn encode(data: Bytes, packet_len: usize) -> Bytes {
-	data.chunks_exact(packet_len).map(|arr| {
+	data.chunks_exact(packet_len).flat_map(|arr| {
 		let mut encoder = EncoderState::default();
 
-		encoder.push_slice(&arr)
-	}).flatten().collect()
+		encoder.push_slice(arr)
+	}).collect()
 }
 
 fn decode(data: Bytes, packet_len: usize) -> Bytes {
 	let mut push_timer = Timer::new("push");
 	let mut read_timer = Timer::new("read");
 
-	data.chunks_exact(packet_len * 2).map(|arr| {
-		let mut decoder = DecoderState::new(packet_len);
+	let mut decoder = DecoderState::new(packet_len);
+	let mut output = vec![0; data.len() / 2];
+
+	for (arr, out) in data.chunks_exact(packet_len * 2).zip(output.chunks_exact_mut(packet_len)) {
+		decoder.reset();
 
 		push_timer.start();
-		decoder.push_slice(&arr);
+		decoder.push_slice(arr);
 		push_timer.stop();
 
 		read_timer.start();
-		let temp = decoder.read();
+		decoder.read_into(out);
 		read_timer.stop();
+	}
 
-		temp
-	}).flatten().collect()
+	output.into()
 }
 
 struct Timer {
@@ -0,0 +1,628 @@
+//! properties of convolutional codes, worked out from their trellis.
+//!
+//! distances are between the encoder outputs for two different inputs, so inverting an output doesn't
+//! change them. the spectrum counts the paths that leave state 0 and first come back to it with each
+//! output weight (A_d), and the total number of 1s input along those paths (B_d), which is what the
+//! error rate bounds are made of.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use rayon::prelude::*;
+
+use crate::channel::db_to_linear;
+use crate::code::Code;
+use crate::poly;
+
+/// how the decoder is given the received symbols, for the error rate bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decisions {
+	/// 0 or 1 for each symbol, so the channel is a binary symmetric one
+	Hard,
+	/// the unquantized values received over an AWGN channel
+	Soft,
+}
+
+/// the number of 1s in the difference between what the encoder outputs for `bit` from `state` and what it
+/// outputs for all 0s
+fn weight(code: &Code, state: u32, bit: u8) -> u32 {
+	(code.output(state, bit) ^ code.output(0, 0)).count_ones()
+}
+
+/// the smallest number of output symbols that differ between the outputs for any two different inputs,
+/// found as the lightest path that leaves state 0 and comes back to it
+pub fn free_distance(code: &Code) -> u32 {
+	let states = code.states();
+
+	// dijkstra, starting from the branch that leaves state 0
+	let mut distances = vec![u32::MAX; states];
+	let mut queue = BinaryHeap::new();
+
+	let first = code.next_state(0, 1);
+	distances[first as usize] = weight(code, 0, 1);
+	queue.push(Reverse((distances[first as usize], first)));
+
+	while let Some(Reverse((distance, state))) = queue.pop() {
+		if state == 0 {
+			return distance;
+		}
+
+		if distance > distances[state as usize] {
+			continue;
+		}
+
+		for bit in 0..2 {
+			let next = code.next_state(state, bit);
+			let next_distance = distance + weight(code, state, bit);
+
+			if next_distance < distances[next as usize] {
+				distances[next as usize] = next_distance;
+				queue.push(Reverse((next_distance, next)));
+			}
+		}
+	}
+
+	unreachable!("state 0 can always be reached again by inputting 0s")
+}
+
+/// the first few terms of a code's distance spectrum, starting from the free distance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spectrum {
+	pub free_distance: u32,
+	/// A_d, the number of paths with output weight d, starting with d = d_free
+	pub paths: Vec<u64>,
+	/// B_d, the total input weight of the paths with output weight d, starting with d = d_free
+	pub input_weights: Vec<u64>,
+}
+
+impl Spectrum {
+	/// A_d, the number of paths with output weight `d` (0 if `d` is past the terms worked out)
+	pub fn paths(&self, d: u32) -> u64 {
+		self.term(&self.paths, d)
+	}
+
+	/// B_d, the total input weight of the paths with output weight `d` (0 if `d` is past the terms worked out)
+	pub fn input_weights(&self, d: u32) -> u64 {
+		self.term(&self.input_weights, d)
+	}
+
+	/// the distances the terms are for
+	pub fn distances(&self) -> impl Iterator<Item = u32> {
+		self.free_distance..self.free_distance + self.paths.len() as u32
+	}
+
+	/// an upper bound on the bit error rate after decoding, for BPSK over an AWGN channel at `eb_n0` (in
+	/// dB) with a code of rate `rate` (which should count any tail that is sent).
+	///
+	/// this is the union bound, sum B_d P_d, where P_d is the chance of picking a path that differs from
+	/// the right one in d symbols. only the terms worked out are summed, so it is only really a bound once
+	/// they are small, which at low Eb/N0 needs more terms than is practical. it's very loose there anyway
+	pub fn bit_error_bound(&self, eb_n0: f64, rate: f64, decisions: Decisions) -> f64 {
+		let sum: f64 = self.distances()
+			.map(|d| self.input_weights(d) as f64 * pairwise_error(d, eb_n0, rate, decisions))
+			.sum();
+
+		sum.min(0.5)
+	}
+
+	/// an upper bound on the chance of a frame of `frame_len` bits having any errors after decoding, see
+	/// [`Spectrum::bit_error_bound`].
+	///
+	/// an error event can start at any of the bits, each with a chance of at most sum A_d P_d
+	pub fn frame_error_bound(&self, eb_n0: f64, rate: f64, frame_len: usize, decisions: Decisions) -> f64 {
+		let event: f64 = self.distances()
+			.map(|d| self.paths(d) as f64 * pairwise_error(d, eb_n0, rate, decisions))
+			.sum();
+
+		(frame_len as f64 * event).min(1.0)
+	}
+
+	fn term(&self, terms: &[u64], d: u32) -> u64 {
+		d.checked_sub(self.free_distance)
+			.and_then(|i| terms.get(i as usize))
+			.copied()
+			.unwrap_or(0)
+	}
+}
+
+/// the chance of the decoder picking a path `d` symbols away from the right one over the other
+fn pairwise_error(d: u32, eb_n0: f64, rate: f64, decisions: Decisions) -> f64 {
+	// the signal to noise ratio of each symbol, which is Es/N0 = R Eb/N0
+	let es_n0 = rate * db_to_linear(eb_n0);
+
+	match decisions {
+		Decisions::Soft => q((2.0 * d as f64 * es_n0).sqrt()),
+		Decisions::Hard => {
+			// more than half of the d symbols have to be wrong, or exactly half and the tie goes the wrong way
+			let p = q((2.0 * es_n0).sqrt());
+			let d = d as u64;
+
+			let wrong = |k: u64| binomial(d, k) * p.powi(k as i32) * (1.0 - p).powi((d - k) as i32);
+			let more: f64 = (d / 2 + 1..=d).map(wrong).sum();
+
+			if d.is_multiple_of(2) {
+				more + wrong(d / 2) / 2.0
+			} else {
+				more
+			}
+		}
+	}
+}
+
+fn binomial(n: u64, k: u64) -> f64 {
+	(0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// the chance of a standard normal variable being more than `x`
+fn q(x: f64) -> f64 {
+	erfc(x / std::f64::consts::SQRT_2) / 2.0
+}
+
+/// the complementary error function, to a relative error of less than 1.2e-7 (from Numerical Recipes)
+fn erfc(x: f64) -> f64 {
+	let z = x.abs();
+	let t = 1.0 / (1.0 + 0.5 * z);
+
+	let poly = -z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418
+		+ t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587
+		+ t * (-0.82215223 + t * 0.17087277))))))));
+
+	let ans = t * poly.exp();
+
+	if x >= 0.0 { ans } else { 2.0 - ans }
+}
+
+/// work out the first `terms` terms of the distance spectrum of `code`, for output weights from d_free
+/// to d_free + terms - 1.
+///
+/// every path that leaves state 0 is followed, one step at a time, until it comes back or gets too
+/// heavy. a catastrophic code has paths that never get heavier, so this panics if that happens
+pub fn distance_spectrum(code: &Code, terms: usize) -> Spectrum {
+	assert!(terms > 0);
+
+	let free_distance = free_distance(code);
+	let limit = free_distance as usize + terms - 1;
+	let states = code.states();
+
+	let mut paths = vec![0u64; terms];
+	let mut input_weights = vec![0u64; terms];
+
+	// (number of paths, their total input weight) for each state and weight so far, indexed
+	// by `state * (limit + 1) + weight`. no path is ever in state 0, as it would be finished
+	let mut current = vec![(0u64, 0u64); states * (limit + 1)];
+	let mut next = current.clone();
+
+	let first = code.next_state(0, 1) as usize;
+	current[first * (limit + 1) + weight(code, 0, 1) as usize] = (1, 1);
+
+	// every path through states other than 0 gets heavier at least once every `states` steps, unless the
+	// code is catastrophic
+	let max_steps = (limit + 1) * states;
+
+	for _ in 0..max_steps {
+		next.fill((0, 0));
+		let mut any = false;
+
+		for state in 1..states {
+			for w in 0..=limit {
+				let (count, inputs) = current[state * (limit + 1) + w];
+
+				if count == 0 {
+					continue;
+				}
+
+				for bit in 0..2u8 {
+					let next_weight = w + weight(code, state as u32, bit) as usize;
+					if next_weight > limit {
+						continue;
+					}
+
+					let next_inputs = inputs + bit as u64 * count;
+					let next_state = code.next_state(state as u32, bit) as usize;
+
+					if next_state == 0 {
+						paths[next_weight - free_distance as usize] += count;
+						input_weights[next_weight - free_distance as usize] += next_inputs;
+					} else {
+						let entry = &mut next[next_state * (limit + 1) + next_weight];
+						entry.0 += count;
+						entry.1 += next_inputs;
+						any = true;
+					}
+				}
+			}
+		}
+
+		if !any {
+			return Spectrum {
+				free_distance,
+				paths,
+				input_weights,
+			};
+		}
+
+		std::mem::swap(&mut current, &mut next);
+	}
+
+	panic!("the code is catastrophic, some paths never get any heavier");
+}
+
+/// a set of FIR filters that get the input of an encoder back from its output without decoding, one per
+/// output of the code. XORing the filtered outputs together gives the input delayed by `delay` bits.
+///
+/// any error in the outputs turns into a few errors in the recovered bits, so this is only any use when
+/// the outputs are known to be right (or for a quick look at noisy data)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedForwardInverse {
+	pub delay: usize,
+	/// the filter for each output, bit `i` tapping the symbol output `i` steps ago
+	pub filters: Vec<u64>,
+	/// the outputs that the code inverts, which are put back before filtering
+	inverted: u8,
+}
+
+impl FeedForwardInverse {
+	/// get the input back from `symbols` (each 0 or not 0), the symbols output for each bit one after
+	/// another starting from state 0. returns one bit (0 or 1) per bit input, less the last `delay`, which
+	/// haven't come out of the filters yet
+	pub fn apply(&self, symbols: &[u8]) -> Vec<u8> {
+		let outputs = self.filters.len();
+		let len = symbols.len() / outputs;
+
+		// the last 64 symbols of each output, bit i being the one from i steps ago
+		let mut history = vec![0u64; outputs];
+
+		let mut bits = Vec::with_capacity(len.saturating_sub(self.delay));
+
+		for (t, each) in symbols.chunks_exact(outputs).enumerate() {
+			let mut bit = 0;
+
+			for (j, symbol) in each.iter().enumerate() {
+				let symbol = (*symbol != 0) ^ (self.inverted & (1 << j) != 0);
+
+				history[j] = (history[j] << 1) | symbol as u64;
+				bit ^= (history[j] & self.filters[j]).count_ones() & 1;
+			}
+
+			if t >= self.delay {
+				bits.push(bit as u8);
+			}
+		}
+
+		bits
+	}
+}
+
+/// find a [`FeedForwardInverse`] for a code, which every code that isn't catastrophic has
+pub fn feed_forward_inverse(code: &Code) -> FeedForwardInverse {
+	assert!(!code.is_catastrophic(), "catastrophic codes don't have a feed-forward inverse");
+
+	let polynomials: Vec<u64> = code.generators().iter()
+		.map(|g| code.taps(*g))
+		.collect();
+
+	let mut filters = vec![0; polynomials.len()];
+	filters[0] = 1;
+	let mut common = polynomials[0];
+
+	// keep `common = sum of filters[j] * polynomials[j]` as the gcd of the generators so far, stopping as
+	// soon as it's a power of D
+	for (j, polynomial) in polynomials.iter().enumerate().skip(1) {
+		if common.count_ones() == 1 {
+			break;
+		}
+
+		let (gcd, x, y) = poly::extended_gcd(common, *polynomial);
+
+		for filter in &mut filters[..j] {
+			*filter = poly::mul(*filter, x);
+		}
+		filters[j] = y;
+		common = gcd;
+	}
+
+	FeedForwardInverse {
+		delay: common.trailing_zeros() as usize,
+		filters,
+		inverted: (0..code.outputs())
+			.filter(|j| code.is_inverted(*j))
+			.fold(0, |acc, j| acc | 1 << j),
+	}
+}
+
+/// a code found by [`search_codes`]
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+	pub code: Code,
+	pub spectrum: Spectrum,
+}
+
+/// the most sets of generators [`search_codes`] will try. searching this many takes around half a
+/// minute on a single core
+pub const MAX_SEARCH_SETS: u64 = 1 << 22;
+
+/// the number of sets of generators [`search_codes`] tries for a constraint length and number of outputs:
+/// every multiset of `outputs` non-zero generators
+pub fn search_sets(constraint_len: u8, outputs: usize) -> u64 {
+	let generators = (1u64 << constraint_len) - 1;
+
+	// (generators + outputs - 1) choose outputs, stopping at the limit so it can't overflow
+	(0..outputs as u64).fold(1u64, |count, i| {
+		(count.saturating_mul(generators + i) / (i + 1)).min(MAX_SEARCH_SETS + 1)
+	})
+}
+
+/// search every rate 1/`outputs` code with constraint length `constraint_len` for the best `count`,
+/// best first. the codes are ranked by free distance, then by fewest paths (A_d) and then by fewest
+/// input bits on those paths (B_d) at each distance in turn, looking at `terms` terms of the spectrum.
+///
+/// the search is exhaustive, apart from leaving out codes that are the same as ones that are tried:
+/// - codes where no generator taps the current input or no generator taps the oldest bit, which are
+///   really codes with a smaller constraint length (possibly delayed)
+/// - codes that are the same but for the order of the outputs or being reversed in time (which doesn't
+///   change their spectrum), of which only the one with the highest generators in octal is tried
+/// - catastrophic codes
+///
+/// the number of codes grows very quickly, so this panics if there are more than [`MAX_SEARCH_SETS`]
+/// sets of generators to try (see [`search_sets`]). that allows up to K=11 at rate 1/2, K=8 at rate 1/3
+/// and K=6 at rate 1/4
+pub fn search_codes(constraint_len: u8, outputs: usize, terms: usize, count: usize) -> Vec<SearchResult> {
+	assert!(constraint_len >= 2);
+	assert!((1..=Code::MAX_OUTPUTS).contains(&outputs));
+	assert!(terms > 0);
+	assert!(
+		search_sets(constraint_len, outputs) <= MAX_SEARCH_SETS,
+		"too many codes to search with K={constraint_len} and {outputs} outputs",
+	);
+
+	let k = constraint_len as u32;
+	let (current, oldest) = (1 << (k - 1), 1);
+
+	let generators: Vec<u32> = (1..1 << k).collect();
+
+	let codes: Vec<(Code, u32)> = generator_sets(&generators, outputs)
+		.par_bridge()
+		.filter(|set| {
+			let taps = set.iter().fold(0, |acc, g| acc | g);
+			taps & current != 0 && taps & oldest != 0
+		})
+		.filter(|set| *set >= reversed(set, k))
+		.map(|set| Code::allowing_catastrophic(constraint_len, &set))
+		.filter(|code| !code.is_catastrophic())
+		.map(|code| {
+			let distance = free_distance(&code);
+			(code, distance)
+		})
+		.collect();
+
+	// only the codes with a free distance at least that of the `count`th best are worth a spectrum
+	let mut distances: Vec<u32> = codes.iter().map(|(_, distance)| *distance).collect();
+	distances.sort_unstable_by(|a, b| b.cmp(a));
+
+	let Some(cutoff) = distances.get(count.min(distances.len()).saturating_sub(1)) else {
+		return Vec::new();
+	};
+
+	let mut results: Vec<SearchResult> = codes.into_par_iter()
+		.filter(|(_, distance)| distance >= cutoff)
+		.map(|(code, _)| {
+			let spectrum = distance_spectrum(&code, terms);
+			SearchResult { code, spectrum }
+		})
+		.collect();
+
+	results.sort_by(|a, b| rank(&a.spectrum, &b.spectrum)
+		.then_with(|| b.code.generators().cmp(a.code.generators())));
+	results.truncate(count);
+
+	results
+}
+
+/// every non-decreasing list of `len` of the `generators`, in order, made as they are needed
+fn generator_sets(generators: &[u32], len: usize) -> impl Iterator<Item = Vec<u32>> + '_ {
+	// indices into `generators`, counted up like an odometer whose digits never decrease
+	let mut indices = vec![0; len];
+	let mut done = generators.is_empty() && len > 0;
+
+	std::iter::from_fn(move || {
+		if done {
+			return None;
+		}
+
+		let set = indices.iter().map(|i| generators[*i]).collect();
+
+		match (0..len).rev().find(|j| indices[*j] + 1 < generators.len()) {
+			Some(j) => {
+				let next = indices[j] + 1;
+				indices[j..].fill(next);
+			}
+			None => done = true,
+		}
+
+		Some(set)
+	})
+}
+
+/// the generators of the same code run backwards in time, sorted
+fn reversed(set: &[u32], constraint_len: u32) -> Vec<u32> {
+	let mut reversed: Vec<u32> = set.iter()
+		.map(|g| g.reverse_bits() >> (32 - constraint_len))
+		.collect();
+
+	reversed.sort_unstable();
+	reversed
+}
+
+/// better spectra first
+fn rank(a: &Spectrum, b: &Spectrum) -> Ordering {
+	b.free_distance.cmp(&a.free_distance)
+		.then_with(|| a.paths.cmp(&b.paths))
+		.then_with(|| a.input_weights.cmp(&b.input_weights))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// the expected values below are the textbook ones (e.g. Lin & Costello, Proakis)
+
+	#[test]
+	fn test_k3() {
+		let code = Code::default();
+		assert_eq!(free_distance(&code), 5);
+
+		// T(D, N) = N D^5 / (1 - 2 N D)
+		let spectrum = distance_spectrum(&code, 5);
+		assert_eq!(spectrum.paths, [1, 2, 4, 8, 16]);
+		assert_eq!(spectrum.input_weights, [1, 4, 12, 32, 80]);
+	}
+
+	#[test]
+	fn test_k3_rate_third() {
+		// T(D, N) = N D^6 / (1 - 2 N D^2)
+		let spectrum = distance_spectrum(&Code::new(3, &[0o4, 0o5, 0o7]), 5);
+
+		assert_eq!(spectrum.free_distance, 6);
+		assert_eq!(spectrum.paths, [1, 0, 2, 0, 4]);
+		assert_eq!(spectrum.input_weights, [1, 0, 4, 0, 12]);
+	}
+
+	#[test]
+	fn test_k7() {
+		let spectrum = distance_spectrum(&Code::new(7, &[0o171, 0o133]), 7);
+
+		assert_eq!(spectrum.free_distance, 10);
+		assert_eq!(spectrum.paths, [11, 0, 38, 0, 193, 0, 1331]);
+		assert_eq!(spectrum.input_weights, [36, 0, 211, 0, 1404, 0, 11633]);
+
+		// inverting an output makes no difference
+		assert_eq!(distance_spectrum(&Code::ccsds(), 7), spectrum);
+	}
+
+	#[test]
+	fn test_accessors() {
+		let spectrum = distance_spectrum(&Code::default(), 3);
+
+		assert_eq!(spectrum.paths(4), 0);
+		assert_eq!(spectrum.paths(6), 2);
+		assert_eq!(spectrum.input_weights(7), 12);
+		assert_eq!(spectrum.paths(8), 0);
+		assert_eq!(spectrum.distances().collect::<Vec<_>>(), [5, 6, 7]);
+	}
+
+	#[test]
+	fn test_q() {
+		assert!((q(0.0) - 0.5).abs() < 1e-7);
+		assert!((q(1.0) - 0.158655).abs() < 1e-6);
+		assert!((q(-1.0) - 0.841345).abs() < 1e-6);
+		assert!((q(4.0) - 3.16712e-5).abs() < 1e-9);
+	}
+
+	#[test]
+	fn test_pairwise_error() {
+		// d = 3 over a BSC is wrong if 2 or 3 symbols are
+		let p = q((2.0 * 0.5 * db_to_linear(3.0)).sqrt());
+		let expected = 3.0 * p * p * (1.0 - p) + p * p * p;
+		assert!((pairwise_error(3, 3.0, 0.5, Decisions::Hard) - expected).abs() < 1e-12);
+
+		// soft decisions are worth about 2 dB
+		assert!(pairwise_error(5, 4.0, 0.5, Decisions::Soft) < pairwise_error(5, 4.0, 0.5, Decisions::Hard));
+	}
+
+	#[test]
+	fn test_bounds_hold_for_simulation() {
+		use crate::decode::DecoderState;
+		use crate::simulate::Simulation;
+
+		let code = Code::default();
+		let spectrum = distance_spectrum(&code, 12);
+		let simulation = Simulation::new(&code, 64).with_target_errors(200);
+
+		for (eb_n0, decisions) in [(4.0, Decisions::Soft), (6.0, Decisions::Hard)] {
+			let simulation = match decisions {
+				Decisions::Soft => simulation.clone(),
+				Decisions::Hard => simulation.clone().with_hard_decisions(),
+			};
+
+			let point = simulation.run(eb_n0, DecoderState::with_code);
+			let ber_bound = spectrum.bit_error_bound(eb_n0, simulation.rate(), decisions);
+			let fer_bound = spectrum.frame_error_bound(eb_n0, simulation.rate(), 64, decisions);
+
+			// above what is measured, but not uselessly far above
+			assert!(point.ber_interval().0 < ber_bound, "{decisions:?} {} {ber_bound}", point.ber());
+			assert!(point.ber() > ber_bound / 10.0, "{decisions:?} {} {ber_bound}", point.ber());
+
+			assert!(point.fer_interval().0 < fer_bound, "{decisions:?} {} {fer_bound}", point.fer());
+		}
+	}
+
+	#[test]
+	fn test_search_codes() {
+		let best = search_codes(3, 2, 4, 1);
+		assert_eq!(best[0].code.generators(), [0o5, 0o7]);
+
+		// the well known K=7 codes are the best there are
+		let best = search_codes(7, 2, 4, 3);
+		assert_eq!(best.len(), 3);
+		assert_eq!(best[0].code.generators(), [0o133, 0o171]);
+		assert_eq!(best[0].spectrum.free_distance, 10);
+		assert!(best[1].spectrum.free_distance <= 10);
+
+		let best = search_codes(5, 3, 2, 1);
+		assert_eq!(best[0].code.generators(), [0o25, 0o33, 0o37]);
+		assert_eq!(best[0].spectrum.free_distance, 12);
+	}
+
+	#[test]
+	fn test_search_sets() {
+		assert_eq!(search_sets(7, 2), 127 * 128 / 2);
+		assert_eq!(search_sets(11, 2), 2047 * 2048 / 2);
+		assert!(search_sets(12, 2) > MAX_SEARCH_SETS);
+		assert!(search_sets(32, 8) > MAX_SEARCH_SETS);
+	}
+
+	#[test]
+	#[should_panic(expected = "too many codes")]
+	fn test_search_too_big() {
+		search_codes(12, 2, 1, 1);
+	}
+
+	#[test]
+	fn test_generator_sets() {
+		let sets: Vec<Vec<u32>> = generator_sets(&[1, 2, 3], 2).collect();
+		assert_eq!(sets, [[1, 1], [1, 2], [1, 3], [2, 2], [2, 3], [3, 3]]);
+		assert_eq!(sets.len() as u64, search_sets(2, 2));
+		assert_eq!(reversed(&[0o133, 0o171], 7), [0o117, 0o155]);
+	}
+
+	fn check_inverse(code: &Code, delay: usize) {
+		let bits: Vec<u8> = (0..200u32).map(|x| (x * 73 % 101 % 2) as u8).collect();
+		let symbols = crate::presets::encode_bits(code, &bits);
+
+		let inverse = feed_forward_inverse(code);
+		assert_eq!(inverse.delay, delay);
+		assert_eq!(inverse.apply(&symbols), bits[..bits.len() - delay]);
+	}
+
+	#[test]
+	fn test_feed_forward_inverse() {
+		let inverse = feed_forward_inverse(&Code::default());
+
+		// (1 + D)(1 + D^2) + D (1 + D + D^2) = 1
+		assert_eq!(inverse.filters, [0b11, 0b10]);
+
+		check_inverse(&Code::default(), 0);
+		check_inverse(&Code::ccsds(), 0);
+		check_inverse(&Code::new(5, &[0o25, 0o33, 0o37]), 0);
+
+		// D (1 + D + D^2) and D (1 + D^2) can only give back D
+		check_inverse(&Code::new(4, &[0o7, 0o5]), 1);
+	}
+
+	#[test]
+	#[should_panic(expected = "catastrophic")]
+	fn test_catastrophic() {
+		// both generators are divisible by 1 + D, so an input of all 1s only outputs a few 1s
+		distance_spectrum(&Code::allowing_catastrophic(3, &[0o6, 0o5]), 3);
+	}
+}
@@ -0,0 +1,510 @@
+//! simulated channels to send encoded symbols through, for testing how well the decoders correct errors.
+//!
+//! channels work on the bytes output by [`EncoderState<u8>`](crate::prelude::EncoderState), each bit
+//! being a symbol sent separately, and give back either hard bytes for [`Decoder::push_slice`] or soft
+//! symbols for [`Decoder::push_soft_slice`] (8 per byte, bit 0 first). every channel is seeded, so the
+//! same seed always gives the same errors.
+//!
+//! [`Decoder::push_slice`]: crate::prelude::Decoder::push_slice
+//! [`Decoder::push_soft_slice`]: crate::prelude::Decoder::push_soft_slice
+
+use std::f64::consts::TAU;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::code;
+
+/// the soft symbol a noiseless ±1 is received as by the channels that add noise, leaving room either side
+/// for the noise before it gets clipped
+pub const SOFT_AMPLITUDE: f64 = 63.0;
+
+/// something symbols can be sent through
+pub trait Channel {
+	/// send one symbol, returning what was received as a soft symbol (see
+	/// [`BitDecoderState::push_soft`](crate::prelude::BitDecoderState::push_soft))
+	fn soft(&mut self, symbol: bool) -> i8;
+
+	/// send one symbol, returning the hard decision on what was received.
+	///
+	/// by default this is the sign of [`Channel::soft`], with a symbol received as exactly 0 being taken as a 0
+	fn hard(&mut self, symbol: bool) -> bool {
+		self.soft(symbol) > 0
+	}
+
+	/// send every bit of `bytes`, returning the hard decisions
+	fn transmit(&mut self, bytes: &[u8]) -> Vec<u8> {
+		bytes.iter()
+			.map(|byte| (0..8).fold(0, |acc, i| acc | (self.hard(byte >> i & 1 != 0) as u8) << i))
+			.collect()
+	}
+
+	/// send every bit of `bytes`, returning 8 soft symbols for each byte (bit 0 first)
+	fn transmit_soft(&mut self, bytes: &[u8]) -> Vec<i8> {
+		bytes.iter()
+			.flat_map(|byte| (0..8).map(move |i| byte >> i & 1 != 0))
+			.map(|symbol| self.soft(symbol))
+			.collect()
+	}
+
+	/// send a serial stream of symbols (each 0 or not 0), like the presets use, returning the soft symbols
+	fn transmit_bits_soft(&mut self, bits: &[u8]) -> Vec<i8> {
+		bits.iter().map(|bit| self.soft(*bit != 0)).collect()
+	}
+}
+
+/// flips each symbol with probability `p`
+#[derive(Debug, Clone)]
+pub struct BinarySymmetric {
+	p: f64,
+	rng: StdRng,
+}
+
+impl BinarySymmetric {
+	pub fn new(p: f64, seed: u64) -> Self {
+		assert!((0.0..=1.0).contains(&p));
+
+		Self {
+			p,
+			rng: StdRng::seed_from_u64(seed),
+		}
+	}
+}
+
+impl Channel for BinarySymmetric {
+	/// a hard channel, so the soft symbols are always as confident as they can be
+	fn soft(&mut self, symbol: bool) -> i8 {
+		code::to_soft(self.hard(symbol) as u8)
+	}
+
+	fn hard(&mut self, symbol: bool) -> bool {
+		symbol ^ self.rng.gen_bool(self.p)
+	}
+}
+
+/// loses each symbol with probability `p`, and gets the others through perfectly
+#[derive(Debug, Clone)]
+pub struct BinaryErasure {
+	p: f64,
+	rng: StdRng,
+}
+
+impl BinaryErasure {
+	pub fn new(p: f64, seed: u64) -> Self {
+		assert!((0.0..=1.0).contains(&p));
+
+		Self {
+			p,
+			rng: StdRng::seed_from_u64(seed),
+		}
+	}
+}
+
+impl Channel for BinaryErasure {
+	/// lost symbols are received as 0
+	fn soft(&mut self, symbol: bool) -> i8 {
+		if self.rng.gen_bool(self.p) {
+			0
+		} else {
+			code::to_soft(symbol as u8)
+		}
+	}
+
+	/// hard decisions can't say a symbol was lost, so they are a coin toss instead
+	fn hard(&mut self, symbol: bool) -> bool {
+		if self.rng.gen_bool(self.p) {
+			self.rng.gen()
+		} else {
+			symbol
+		}
+	}
+}
+
+/// how symbols are mapped onto the carrier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Modulation {
+	/// one symbol per channel use
+	Bpsk,
+	/// two symbols per channel use, one on I and one on Q (gray coded)
+	Qpsk,
+}
+
+impl Modulation {
+	/// the number of symbols sent per channel use
+	pub fn bits_per_symbol(self) -> usize {
+		match self {
+			Modulation::Bpsk => 1,
+			Modulation::Qpsk => 2,
+		}
+	}
+}
+
+/// additive white gaussian noise: every symbol is sent as ±1 and has gaussian noise added to it.
+///
+/// with gray coding QPSK is just BPSK on I and Q, so the modulation only matters when the noise is given
+/// as Es/N0 (energy per channel use) rather than Eb/N0 (energy per bit of information)
+#[derive(Debug, Clone)]
+pub struct Awgn {
+	/// the standard deviation of the noise
+	sigma: f64,
+	rng: StdRng,
+}
+
+impl Awgn {
+	/// noise for an Eb/N0 of `eb_n0` dB, where `rate` is the rate of the code (bits input per symbol output,
+	/// e.g. 0.5) so the energy spent on each bit is shared between its symbols
+	pub fn new(eb_n0: f64, rate: f64, seed: u64) -> Self {
+		assert!(rate > 0.0 && rate <= 1.0);
+
+		// each symbol gets `rate` of a bit's energy, and the noise variance is N0 / 2
+		let es_n0 = rate * db_to_linear(eb_n0);
+		Self::with_sigma((1.0 / (2.0 * es_n0)).sqrt(), seed)
+	}
+
+	/// noise for an Es/N0 of `es_n0` dB, the energy per channel use
+	pub fn from_es_n0(es_n0: f64, modulation: Modulation, seed: u64) -> Self {
+		// a QPSK channel use carries 2 symbols, so each gets half the energy
+		let bits = modulation.bits_per_symbol() as f64;
+		Self::with_sigma((bits / (2.0 * db_to_linear(es_n0))).sqrt(), seed)
+	}
+
+	/// noise with a standard deviation of `sigma`, relative to symbols sent as ±1
+	pub fn with_sigma(sigma: f64, seed: u64) -> Self {
+		assert!(sigma >= 0.0);
+
+		Self {
+			sigma,
+			rng: StdRng::seed_from_u64(seed),
+		}
+	}
+
+	pub fn sigma(&self) -> f64 {
+		self.sigma
+	}
+
+	/// a sample of standard normal noise (box muller)
+	fn gaussian(&mut self) -> f64 {
+		let u: f64 = 1.0 - self.rng.gen::<f64>();
+		let v: f64 = self.rng.gen();
+
+		(-2.0 * u.ln()).sqrt() * (TAU * v).cos()
+	}
+
+	/// what is received when `sent` is sent
+	fn receive(&mut self, sent: f64) -> f64 {
+		sent + self.sigma * self.gaussian()
+	}
+}
+
+impl Channel for Awgn {
+	fn soft(&mut self, symbol: bool) -> i8 {
+		quantize(self.receive(bpsk(symbol)))
+	}
+}
+
+/// a two state burst error channel: in the good state few symbols are flipped, and in the bad state
+/// many are. it moves between the states at random, so the errors come in bursts
+#[derive(Debug, Clone)]
+pub struct GilbertElliott {
+	/// the chance of moving from good to bad before each symbol
+	to_bad: f64,
+	/// the chance of moving from bad to good before each symbol
+	to_good: f64,
+	/// the chance of flipping a symbol in the good and bad states
+	error_good: f64,
+	error_bad: f64,
+	bad: bool,
+	rng: StdRng,
+}
+
+impl GilbertElliott {
+	/// a channel starting in the good state. bursts last `1 / to_good` symbols on average, with
+	/// `1 / to_bad` symbols between them
+	pub fn new(to_bad: f64, to_good: f64, error_good: f64, error_bad: f64, seed: u64) -> Self {
+		for p in [to_bad, to_good, error_good, error_bad] {
+			assert!((0.0..=1.0).contains(&p));
+		}
+
+		Self {
+			to_bad,
+			to_good,
+			error_good,
+			error_bad,
+			bad: false,
+			rng: StdRng::seed_from_u64(seed),
+		}
+	}
+
+	/// the classic gilbert channel, with no errors in the good state and half the symbols wrong in the bad one
+	pub fn gilbert(to_bad: f64, to_good: f64, seed: u64) -> Self {
+		Self::new(to_bad, to_good, 0.0, 0.5, seed)
+	}
+
+	/// whether the last symbol was sent in the bad state
+	pub fn is_bad(&self) -> bool {
+		self.bad
+	}
+
+	/// the fraction of symbols flipped in the long run
+	pub fn error_rate(&self) -> f64 {
+		let bad = self.to_bad / (self.to_bad + self.to_good);
+
+		bad * self.error_bad + (1.0 - bad) * self.error_good
+	}
+}
+
+impl Channel for GilbertElliott {
+	/// a hard channel, so the soft symbols are always as confident as they can be
+	fn soft(&mut self, symbol: bool) -> i8 {
+		code::to_soft(self.hard(symbol) as u8)
+	}
+
+	fn hard(&mut self, symbol: bool) -> bool {
+		let change = if self.bad { self.to_good } else { self.to_bad };
+		self.bad ^= self.rng.gen_bool(change);
+
+		let error = if self.bad { self.error_bad } else { self.error_good };
+		symbol ^ self.rng.gen_bool(error)
+	}
+}
+
+/// how the amplitude of a fading channel is distributed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fading {
+	/// no line of sight, only scattered paths
+	Rayleigh,
+	/// a line of sight path `k` times as strong as the scattered ones (the rician K factor, not in dB)
+	Rician { k: f64 },
+}
+
+/// flat block fading: the amplitude of the signal changes at random every `block_len` symbols (staying the
+/// same in between), then gaussian noise is added. the average power is the same as without fading.
+///
+/// with channel state information the receiver knows the amplitude, and scales each soft symbol by it so
+/// that symbols received in a deep fade count for less. [`BlockFading::gain`] gives the amplitude too,
+/// for doing something else with it
+#[derive(Debug, Clone)]
+pub struct BlockFading {
+	fading: Fading,
+	block_len: usize,
+	noise: Awgn,
+	channel_state: bool,
+	gain: f64,
+	/// how many more symbols are sent with the current gain
+	remaining: usize,
+}
+
+impl BlockFading {
+	/// fading with noise for an average Eb/N0 of `eb_n0` dB (see [`Awgn::new`])
+	pub fn new(fading: Fading, block_len: usize, eb_n0: f64, rate: f64, seed: u64) -> Self {
+		Self::with_noise(fading, block_len, Awgn::new(eb_n0, rate, seed))
+	}
+
+	/// fading before the noise from `noise` is added (it supplies the randomness for the fading too)
+	pub fn with_noise(fading: Fading, block_len: usize, noise: Awgn) -> Self {
+		assert!(block_len > 0);
+
+		if let Fading::Rician { k } = fading {
+			assert!(k >= 0.0);
+		}
+
+		Self {
+			fading,
+			block_len,
+			noise,
+			channel_state: false,
+			gain: 1.0,
+			remaining: 0,
+		}
+	}
+
+	/// scale the soft symbols by the amplitude they were received with
+	pub fn with_channel_state(mut self) -> Self {
+		self.channel_state = true;
+		self
+	}
+
+	/// the amplitude the last symbol was sent with
+	pub fn gain(&self) -> f64 {
+		self.gain
+	}
+
+	/// send every bit of `bytes`, returning 8 soft symbols for each byte (bit 0 first) and the amplitude
+	/// each was sent with
+	pub fn transmit_soft_with_gains(&mut self, bytes: &[u8]) -> (Vec<i8>, Vec<f64>) {
+		bytes.iter()
+			.flat_map(|byte| (0..8).map(move |i| byte >> i & 1 != 0))
+			.map(|symbol| (self.soft(symbol), self.gain))
+			.unzip()
+	}
+
+	/// a new random amplitude, with a mean square of 1
+	fn next_gain(&mut self) -> f64 {
+		let (line_of_sight, scattered) = match self.fading {
+			Fading::Rayleigh => (0.0, 1.0),
+			Fading::Rician { k } => ((k / (k + 1.0)).sqrt(), 1.0 / (k + 1.0)),
+		};
+
+		// a complex gaussian with `scattered` power, plus the line of sight path
+		let deviation = (scattered / 2.0).sqrt();
+		let i = line_of_sight + deviation * self.noise.gaussian();
+		let q = deviation * self.noise.gaussian();
+
+		i.hypot(q)
+	}
+}
+
+impl Channel for BlockFading {
+	fn soft(&mut self, symbol: bool) -> i8 {
+		if self.remaining == 0 {
+			self.gain = self.next_gain();
+			self.remaining = self.block_len;
+		}
+
+		self.remaining -= 1;
+
+		let received = self.noise.receive(self.gain * bpsk(symbol));
+
+		if self.channel_state {
+			quantize(received * self.gain)
+		} else {
+			quantize(received)
+		}
+	}
+}
+
+/// a symbol as sent with BPSK
+fn bpsk(symbol: bool) -> f64 {
+	if symbol { 1.0 } else { -1.0 }
+}
+
+/// a received value (where ±1 was sent) as a soft symbol, clipping it if it's too far out
+pub fn quantize(received: f64) -> i8 {
+	(received * SOFT_AMPLITUDE).round().clamp(-127.0, 127.0) as i8
+}
+
+/// turn decibels into a linear ratio
+pub fn db_to_linear(db: f64) -> f64 {
+	10f64.powf(db / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn errors(sent: &[u8], received: &[u8]) -> u32 {
+		sent.iter().zip(received).map(|(a, b)| (a ^ b).count_ones()).sum()
+	}
+
+	#[test]
+	fn test_binary_symmetric() {
+		let data = vec![0x5A; 10_000];
+		let received = BinarySymmetric::new(0.1, 1).transmit(&data);
+
+		// around 8000 errors
+		let errors = errors(&data, &received);
+		assert!((7600..8400).contains(&errors), "{errors}");
+
+		// the same seed gives the same errors
+		assert_eq!(BinarySymmetric::new(0.1, 1).transmit(&data), received);
+		assert_ne!(BinarySymmetric::new(0.1, 2).transmit(&data), received);
+	}
+
+	#[test]
+	fn test_binary_erasure() {
+		let data = vec![0xC3; 10_000];
+		let received = BinaryErasure::new(0.2, 1).transmit_soft(&data);
+
+		let erased = received.iter().filter(|symbol| **symbol == 0).count();
+		assert!((15_000..17_000).contains(&erased), "{erased}");
+
+		// everything that got through is right
+		let expected = BinarySymmetric::new(0.0, 0).transmit_soft(&data);
+		assert!(received.iter().zip(&expected).all(|(r, e)| *r == 0 || r == e));
+	}
+
+	#[test]
+	fn test_awgn_sigma() {
+		// rate 1/2 at 0dB means Es/N0 is 1/2, so the variance is 1
+		assert!((Awgn::new(0.0, 0.5, 0).sigma() - 1.0).abs() < 1e-12);
+
+		// QPSK needs twice the energy per channel use for the same noise per symbol
+		let bpsk = Awgn::from_es_n0(3.0, Modulation::Bpsk, 0).sigma();
+		let qpsk = Awgn::from_es_n0(3.0 + 10.0 * 2f64.log10(), Modulation::Qpsk, 0).sigma();
+		assert!((bpsk - qpsk).abs() < 1e-12);
+	}
+
+	#[test]
+	fn test_awgn_uncoded_error_rate() {
+		// uncoded BPSK at 4dB has a bit error rate of about 0.0125
+		let data = vec![0x00; 50_000];
+		let received = Awgn::new(4.0, 1.0, 3).transmit(&data);
+
+		let rate = errors(&data, &received) as f64 / (data.len() * 8) as f64;
+		assert!((0.011..0.014).contains(&rate), "{rate}");
+	}
+
+	#[test]
+	fn test_gilbert_elliott() {
+		let data = vec![0x00; 20_000];
+		let mut channel = GilbertElliott::gilbert(0.002, 0.1, 5);
+
+		// 1/51 of the time in the bad state, getting half the symbols wrong
+		assert!((channel.error_rate() - 0.5 / 51.0).abs() < 1e-12);
+
+		let received = channel.transmit(&data);
+		let symbols: Vec<bool> = received.iter()
+			.flat_map(|byte| (0..8).map(move |i| byte >> i & 1 != 0))
+			.collect();
+
+		let errors = symbols.iter().filter(|error| **error).count();
+		let rate = errors as f64 / symbols.len() as f64;
+		assert!((0.007..0.013).contains(&rate), "{rate}");
+
+		// an error is far more likely straight after another one than on average
+		let after_error = symbols.windows(2).filter(|pair| pair[0] && pair[1]).count();
+		assert!(after_error as f64 / errors as f64 > 0.3);
+	}
+
+	#[test]
+	fn test_fading_gains() {
+		for (fading, spread) in [(Fading::Rayleigh, 0.2), (Fading::Rician { k: 100.0 }, 0.01)] {
+			let mut channel = BlockFading::new(fading, 4, 10.0, 0.5, 9);
+			let (_, gains) = channel.transmit_soft_with_gains(&[0x55; 4000]);
+
+			// the same within each block
+			assert!(gains.chunks(4).all(|block| block.iter().all(|gain| *gain == block[0])));
+
+			// an average power of 1
+			let power = gains.iter().map(|gain| gain * gain).sum::<f64>() / gains.len() as f64;
+			assert!((power - 1.0).abs() < 0.05, "{fading:?} {power}");
+
+			// the spread of the power, which is a lot more with no line of sight
+			let variance = gains.iter().map(|gain| (gain * gain - 1.0).powi(2)).sum::<f64>() / gains.len() as f64;
+			assert!(variance > spread && variance < spread * 10.0, "{fading:?} {variance}");
+		}
+	}
+
+	#[test]
+	fn test_fading_channel_state() {
+		// with the same seed the same gains and noise are used, only the scaling is different
+		let mut without = BlockFading::new(Fading::Rayleigh, 8, 20.0, 1.0, 2);
+		let mut with = BlockFading::new(Fading::Rayleigh, 8, 20.0, 1.0, 2).with_channel_state();
+
+		for _ in 0..100 {
+			let a = without.soft(true);
+			let b = with.soft(true);
+
+			assert_eq!(without.gain(), with.gain());
+			assert!((b as f64 - a as f64 * with.gain()).abs() <= 1.0 + with.gain() || b.abs() == 127);
+		}
+	}
+
+	#[test]
+	fn test_quantize() {
+		assert_eq!(quantize(1.0), 63);
+		assert_eq!(quantize(-0.5), -32);
+		assert_eq!(quantize(5.0), 127);
+		assert_eq!(quantize(-5.0), -127);
+	}
+}
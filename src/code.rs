@@ -0,0 +1,541 @@
+use rayon::prelude::*;
+
+use crate::poly;
+
+/// the definition of a rate 1/n convolutional code.
+///
+/// generators are written the way they usually are in octal: the most significant bit (bit `K - 1`)
+/// taps the bit currently being input and the least significant bit taps the oldest one.
+/// so the default K=3 code is `Code::new(3, &[0o5, 0o7])`.
+///
+/// for more detail on how this works see [this video](https://youtu.be/kRIfpmiMCpU)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Code {
+	constraint_len: u8,
+	generators: Vec<u32>,
+	/// bit `j` is set if output `j` is inverted after encoding
+	inverted: u8,
+}
+
+impl Code {
+	/// the largest constraint length that can be represented
+	pub const MAX_CONSTRAINT_LEN: u8 = 32;
+
+	/// the largest number of outputs (n in rate 1/n) that can be represented
+	pub const MAX_OUTPUTS: usize = 8;
+
+	/// create a code from its constraint length (K) and generator polynomials, one per output.
+	///
+	/// panics if the code is catastrophic (see [`Code::is_catastrophic`])
+	pub fn new(constraint_len: u8, generators: &[u32]) -> Self {
+		let code = Self::allowing_catastrophic(constraint_len, generators);
+
+		assert!(
+			!code.is_catastrophic(),
+			"the code is catastrophic (its generators have a common factor), so a few errors could cause endless decoding errors",
+		);
+
+		code
+	}
+
+	/// the same as [`Code::new`], but allowing catastrophic codes. they are no use for sending data, but
+	/// can still be looked at
+	pub fn allowing_catastrophic(constraint_len: u8, generators: &[u32]) -> Self {
+		assert!(constraint_len >= 2, "constraint length must be at least 2");
+		assert!(constraint_len <= Self::MAX_CONSTRAINT_LEN, "constraint length can be at most {}", Self::MAX_CONSTRAINT_LEN);
+		assert!(!generators.is_empty(), "a code needs at least one generator");
+		assert!(generators.len() <= Self::MAX_OUTPUTS, "a code can have at most {} generators", Self::MAX_OUTPUTS);
+
+		for g in generators {
+			assert!(*g != 0, "generators can't be 0");
+			assert!((*g as u64) < (1 << constraint_len), "generator {g:o} is too long for K={constraint_len}");
+		}
+
+		Self {
+			constraint_len,
+			generators: generators.to_vec(),
+			inverted: 0,
+		}
+	}
+
+	/// invert output `output` (counting from 0) after encoding, as some standards do
+	pub fn with_inverted(mut self, output: usize) -> Self {
+		assert!(output < self.generators.len());
+
+		self.inverted |= 1 << output;
+		self
+	}
+
+	/// K=7, rate 1/2 (171, 133) code with the second output inverted, as used by CCSDS (and NASA)
+	pub fn ccsds() -> Self {
+		Self::new(7, &[0o171, 0o133]).with_inverted(1)
+	}
+
+	/// the constraint length (K)
+	pub fn constraint_len(&self) -> u8 {
+		self.constraint_len
+	}
+
+	pub fn generators(&self) -> &[u32] {
+		&self.generators
+	}
+
+	/// true if output `output` is inverted after encoding
+	pub fn is_inverted(&self, output: usize) -> bool {
+		self.inverted & (1 << output) != 0
+	}
+
+	/// the number of symbols output per bit input (n in rate 1/n)
+	pub fn outputs(&self) -> usize {
+		self.generators.len()
+	}
+
+	/// the number of states the encoder can be in (2^(K - 1))
+	pub fn states(&self) -> usize {
+		1 << (self.constraint_len - 1)
+	}
+
+	/// the state after inputting `bit` while in `state`.
+	///
+	/// bit 0 of a state is the most recently input bit
+	#[inline]
+	pub fn next_state(&self, state: u32, bit: u8) -> u32 {
+		let mask = (self.states() - 1) as u32;
+		((state << 1) | bit as u32) & mask
+	}
+
+	/// the symbols output when inputting `bit` while in `state`, output `j` being bit `j`
+	pub fn output(&self, state: u32, bit: u8) -> u8 {
+		let register = ((state as u64) << 1) | bit as u64;
+
+		let mut ans = self.inverted;
+		for (j, g) in self.generators.iter().enumerate() {
+			ans ^= (parity(register & self.taps(*g)) as u8) << j;
+		}
+
+		ans
+	}
+
+	/// true if some input with infinitely many 1s makes the encoder output only finitely many 1s, so a
+	/// finite number of channel errors could make the decoder get infinitely many bits wrong.
+	///
+	/// this is the case when the generator polynomials have a common factor other than a power of D
+	pub fn is_catastrophic(&self) -> bool {
+		let common = self.generators.iter()
+			.map(|g| self.taps(*g))
+			.fold(0, poly::gcd);
+
+		common.count_ones() != 1
+	}
+
+	/// true if inverting every input bit inverts every output, which is when every generator has an odd
+	/// number of taps. a decoder can't tell an inverted stream of symbols (e.g. from a 180° phase error)
+	/// from one that isn't, and just outputs the inverted bits
+	pub fn is_transparent(&self) -> bool {
+		self.generators.iter().all(|g| g.count_ones() % 2 == 1)
+	}
+
+	/// the generator, reversed so that bit `d` taps the bit input `d` steps ago. this is also the
+	/// generator as a polynomial in D, bit `i` being the coefficient of D^i
+	#[inline]
+	pub(crate) fn taps(&self, generator: u32) -> u64 {
+		(generator as u64).reverse_bits() >> (64 - self.constraint_len as u32)
+	}
+}
+
+impl Default for Code {
+	/// the K=3, rate 1/2 (5, 7) code
+	fn default() -> Self {
+		Self::new(3, &[0o5, 0o7])
+	}
+}
+
+#[inline]
+fn parity(x: u64) -> u32 {
+	x.count_ones() & 1
+}
+
+/// how far a soft symbol is from a confident `expected` (0 to 255)
+#[inline]
+fn soft_cost(symbol: i8, expected: bool) -> u32 {
+	let symbol = symbol as i32;
+
+	let cost = if expected {
+		127 - symbol
+	} else {
+		127 + symbol
+	};
+
+	cost.max(0) as u32
+}
+
+/// the soft symbol for a hard bit (0 or not 0), as confident as it can be
+#[inline]
+pub(crate) fn to_soft(bit: u8) -> i8 {
+	if bit != 0 { 127 } else { -127 }
+}
+
+/// lookup tables for stepping through a code's trellis, shared between the decoders
+#[derive(Debug)]
+pub(crate) struct Trellis {
+	code: Code,
+	states: usize,
+	/// indexed by `state << 1 | bit`
+	outputs: Vec<u8>,
+}
+
+impl Trellis {
+	/// marks a state that no path has reached
+	pub const UNREACHED: u32 = u32::MAX;
+
+	/// the number of states from which [`Trellis::acs_decisions`] is split between threads
+	const PARALLEL_STATES: usize = 1 << 11;
+
+	/// the number of states each thread works on at a time (a multiple of 64)
+	const PARALLEL_CHUNK: usize = 1 << 10;
+
+	pub fn new(code: &Code) -> Self {
+		assert!(code.constraint_len() <= 16, "viterbi decoding is limited to K <= 16");
+
+		let states = code.states();
+		let outputs = (0..states * 2)
+			.map(|i| code.output((i >> 1) as u32, (i & 1) as u8))
+			.collect();
+
+		Self {
+			code: code.clone(),
+			states,
+			outputs,
+		}
+	}
+
+	pub fn code(&self) -> &Code {
+		&self.code
+	}
+
+	pub fn states(&self) -> usize {
+		self.states
+	}
+
+	/// the number of different symbol combinations a branch can output
+	pub fn symbols(&self) -> usize {
+		1 << self.code.outputs()
+	}
+
+	#[inline]
+	pub fn output(&self, state: usize, bit: usize) -> usize {
+		self.outputs[(state << 1) | bit] as usize
+	}
+
+	/// the cost of each possible branch output (the hamming distance) given the received symbols.
+	///
+	/// each received symbol is either 0 or not 0
+	pub fn hard_metrics(&self, symbols: &[u8], metrics: &mut [u32]) {
+		debug_assert_eq!(symbols.len(), self.code.outputs());
+
+		let received = symbols.iter()
+			.enumerate()
+			.fold(0, |acc, (j, s)| acc | ((*s != 0) as u32) << j);
+
+		for (expected, metric) in metrics[..self.symbols()].iter_mut().enumerate() {
+			*metric = (received ^ expected as u32).count_ones();
+		}
+	}
+
+	/// the cost of each possible branch output given the received soft symbols.
+	///
+	/// a soft symbol is positive for a 1 and negative for a 0, the further from 0 the more confident.
+	/// 0 means nothing is known about the symbol (an erasure), so it costs the same either way
+	pub fn soft_metrics(&self, symbols: &[i8], metrics: &mut [u32]) {
+		debug_assert_eq!(symbols.len(), self.code.outputs());
+
+		for (expected, metric) in metrics[..self.symbols()].iter_mut().enumerate() {
+			*metric = symbols.iter()
+				.enumerate()
+				.map(|(j, s)| soft_cost(*s, (expected >> j) & 1 != 0))
+				.sum();
+		}
+	}
+
+	/// add, compare, select.
+	///
+	/// for every state, find the cheapest way of getting there from the previous step. `cost` gives the
+	/// cost of being in a state at the previous step, `metrics` the cost of each branch output, and `select`
+	/// is called with the state, the state it came from, and the new cost. states that can't be reached
+	/// yet are skipped.
+	///
+	/// when both ways cost the same, the lower previous state is picked
+	#[inline]
+	pub fn acs(&self, metrics: &[u32], cost: impl Fn(usize) -> u32, mut select: impl FnMut(usize, usize, u32)) {
+		let half = self.states / 2;
+
+		for next in 0..self.states {
+			let bit = next & 1;
+			let prev0 = next >> 1;
+			let prev1 = prev0 | half;
+
+			let cost0 = cost(prev0).saturating_add(metrics[self.output(prev0, bit)]);
+			let cost1 = cost(prev1).saturating_add(metrics[self.output(prev1, bit)]);
+
+			if cost1 < cost0 {
+				select(next, prev1, cost1);
+			} else if cost0 != Self::UNREACHED {
+				select(next, prev0, cost0);
+			}
+		}
+	}
+
+	/// the same as [`Trellis::acs`], but writing the new cost of every state to `new_costs` and recording
+	/// which way it was reached as one bit per state in `decisions` (set if it came from the upper half of
+	/// the states, so the previous state can be worked out again when tracing back).
+	///
+	/// `decisions` needs a word for every 64 states. with a lot of states the work is split between threads
+	pub fn acs_decisions(&self, metrics: &[u32], costs: &[u32], new_costs: &mut [u32], decisions: &mut [u64]) {
+		debug_assert_eq!(decisions.len(), self.states.div_ceil(64));
+
+		if self.states >= Self::PARALLEL_STATES {
+			new_costs.par_chunks_mut(Self::PARALLEL_CHUNK)
+				.zip(decisions.par_chunks_mut(Self::PARALLEL_CHUNK / 64))
+				.enumerate()
+				.for_each(|(i, (new_costs, decisions))| {
+					self.acs_range(metrics, costs, i * Self::PARALLEL_CHUNK, new_costs, decisions);
+				});
+		} else {
+			self.acs_range(metrics, costs, 0, new_costs, decisions);
+		}
+	}
+
+	/// [`Trellis::acs_decisions`] for the states from `first` onwards, as many as there are `new_costs`
+	#[inline]
+	fn acs_range(&self, metrics: &[u32], costs: &[u32], first: usize, new_costs: &mut [u32], decisions: &mut [u64]) {
+		let half = self.states / 2;
+
+		decisions.fill(0);
+
+		for (i, new_cost) in new_costs.iter_mut().enumerate() {
+			let next = first + i;
+			let bit = next & 1;
+			let prev0 = next >> 1;
+			let prev1 = prev0 | half;
+
+			let cost0 = costs[prev0].saturating_add(metrics[self.output(prev0, bit)]);
+			let cost1 = costs[prev1].saturating_add(metrics[self.output(prev1, bit)]);
+
+			if cost1 < cost0 {
+				*new_cost = cost1;
+				decisions[i / 64] |= 1 << (i % 64);
+			} else {
+				*new_cost = cost0;
+			}
+		}
+	}
+
+	/// keep path costs from overflowing on long streams by taking the cheapest cost away from all of
+	/// them once it gets big. only the differences between costs matter, so this changes nothing else.
+	///
+	/// returns how much was taken away (0 if nothing was)
+	pub fn normalize(costs: &mut [u32]) -> u32 {
+		const NORMALIZE_AT: u32 = 1 << 30;
+
+		let min = *costs.iter().min().unwrap();
+
+		if min < NORMALIZE_AT {
+			return 0;
+		}
+
+		for cost in costs.iter_mut().filter(|cost| **cost != Self::UNREACHED) {
+			*cost -= min;
+		}
+
+		min
+	}
+
+	/// the costs of being in each state before anything has been pushed (the encoder starts in state 0)
+	pub fn start_costs(&self) -> Vec<u32> {
+		let mut costs = vec![0; self.states];
+		Self::reset_costs(&mut costs);
+		costs
+	}
+
+	/// set `costs` back to [`Trellis::start_costs`] in place, so resetting a decoder doesn't allocate
+	pub fn reset_costs(costs: &mut [u32]) {
+		costs.fill(Self::UNREACHED);
+		costs[0] = 0;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_default_outputs() {
+		// the K=3 code that EncoderState used to have built in
+		let code = Code::default();
+
+		for state in 0..4u32 {
+			for bit in 0..2u8 {
+				let old = (state & 1) as u8;
+				let older = ((state >> 1) & 1) as u8;
+
+				let s0 = older ^ bit;
+				let s1 = old ^ older ^ bit;
+
+				assert_eq!(code.output(state, bit), s1 << 1 | s0);
+			}
+		}
+	}
+
+	#[test]
+	fn test_next_state() {
+		let code = Code::default();
+
+		assert_eq!(code.next_state(0, 1), 1);
+		assert_eq!(code.next_state(1, 1), 3);
+		assert_eq!(code.next_state(3, 0), 2);
+		assert_eq!(code.next_state(2, 0), 0);
+	}
+
+	#[test]
+	fn test_taps() {
+		let code = Code::new(7, &[0o171, 0o133]);
+
+		assert_eq!(code.taps(0o171), 0b1001111);
+		assert_eq!(code.taps(0o133), 0b1101101);
+	}
+
+	#[test]
+	fn test_inverted() {
+		let code = Code::ccsds();
+
+		assert!(!code.is_inverted(0));
+		assert!(code.is_inverted(1));
+		assert_eq!(code.output(0, 0), 0b10);
+	}
+
+	#[test]
+	fn test_hard_metrics() {
+		let trellis = Trellis::new(&Code::default());
+		let mut metrics = [0; 4];
+
+		trellis.hard_metrics(&[0, 0], &mut metrics);
+		assert_eq!(metrics, [0, 1, 1, 2]);
+
+		trellis.hard_metrics(&[0xFF, 0], &mut metrics);
+		assert_eq!(metrics, [1, 0, 2, 1]);
+
+		trellis.hard_metrics(&[0, 0x10], &mut metrics);
+		assert_eq!(metrics, [1, 2, 0, 1]);
+	}
+
+	#[test]
+	fn test_soft_metrics() {
+		let trellis = Trellis::new(&Code::default());
+		let mut metrics = [0; 4];
+
+		trellis.soft_metrics(&[to_soft(0), to_soft(1)], &mut metrics);
+		assert_eq!(metrics, [254, 508, 0, 254]);
+
+		// erasures don't favour anything
+		trellis.soft_metrics(&[0, 0], &mut metrics);
+		assert_eq!(metrics, [254; 4]);
+
+		trellis.soft_metrics(&[10, -100], &mut metrics);
+		assert_eq!(metrics, [137 + 27, 117 + 27, 137 + 227, 117 + 227]);
+	}
+
+	#[test]
+	fn test_acs() {
+		let trellis = Trellis::new(&Code::default());
+		let costs = [0, 3, 3, 5];
+		let mut selected = vec![];
+
+		// every branch costs the same, so the cheapest previous state always wins, and ties go to the lower state
+		trellis.acs(&[1; 4], |state| costs[state], |state, prev, cost| selected.push((state, prev, cost)));
+		assert_eq!(selected, [(0, 0, 1), (1, 0, 1), (2, 1, 4), (3, 1, 4)]);
+
+		let costs = [0, 3, 0, 3];
+		let mut selected = vec![];
+
+		trellis.acs(&[1; 4], |state| costs[state], |state, prev, _| selected.push((state, prev)));
+		assert_eq!(selected, [(0, 0), (1, 0), (2, 1), (3, 1)]);
+	}
+
+	#[test]
+	fn test_acs_decisions() {
+		// enough states to be split between threads
+		let trellis = Trellis::new(&Code::new(13, &[0o15627, 0o12363]));
+		let states = trellis.states();
+
+		let costs: Vec<u32> = (0..states as u32).map(|state| state.wrapping_mul(2654435761) >> 24).collect();
+		let metrics = [3, 0, 7, 2];
+
+		let mut new_costs = vec![0; states];
+		let mut decisions = vec![0; states / 64];
+		trellis.acs_decisions(&metrics, &costs, &mut new_costs, &mut decisions);
+
+		let mut expected_costs = vec![Trellis::UNREACHED; states];
+		let mut expected_decisions = vec![0u64; states / 64];
+		trellis.acs(&metrics, |state| costs[state], |state, prev, cost| {
+			expected_costs[state] = cost;
+
+			if prev >= states / 2 {
+				expected_decisions[state / 64] |= 1 << (state % 64);
+			}
+		});
+
+		assert_eq!(new_costs, expected_costs);
+		assert_eq!(decisions, expected_decisions);
+	}
+
+	#[test]
+	fn test_acs_unreached() {
+		let trellis = Trellis::new(&Code::default());
+		let costs = trellis.start_costs();
+		let mut selected = vec![];
+
+		trellis.acs(&[0; 4], |state| costs[state], |state, prev, _| selected.push((state, prev)));
+		assert_eq!(selected, [(0, 0), (1, 0)]);
+	}
+
+	#[test]
+	fn test_normalize() {
+		let mut costs = [5, 8, Trellis::UNREACHED, 6];
+		assert_eq!(Trellis::normalize(&mut costs), 0);
+		assert_eq!(costs, [5, 8, Trellis::UNREACHED, 6]);
+
+		let mut costs = [(1 << 30) + 5, (1 << 30) + 8, Trellis::UNREACHED, 1 << 30];
+		assert_eq!(Trellis::normalize(&mut costs), 1 << 30);
+		assert_eq!(costs, [5, 8, Trellis::UNREACHED, 0]);
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_generator_too_long() {
+		Code::new(3, &[0o17, 0o5]);
+	}
+
+	#[test]
+	fn test_catastrophic() {
+		// 1 + D and 1 + D^2 = (1 + D)^2
+		assert!(Code::allowing_catastrophic(3, &[0o6, 0o5]).is_catastrophic());
+
+		// a common factor of D is just a delay, which is fine
+		assert!(!Code::allowing_catastrophic(4, &[0o7, 0o5]).is_catastrophic());
+
+		assert!(!Code::default().is_catastrophic());
+		assert!(!Code::ccsds().is_catastrophic());
+	}
+
+	#[test]
+	fn test_transparent() {
+		assert!(Code::ccsds().is_transparent());
+		assert!(!Code::default().is_transparent());
+	}
+
+	#[test]
+	#[should_panic(expected = "catastrophic")]
+	fn test_new_rejects_catastrophic() {
+		Code::new(3, &[0o6, 0o5]);
+	}
+}
@@ -68,6 +68,25 @@ pub fn state_to_bit(state: u8, bit: u8) -> u8 {
 	map_to(state & BIT_MASK[0], bit)
 }
 
+/// split bytes into bits (each 0 or 1), most significant bit first
+pub fn unpack_bits(bytes: &[u8]) -> Vec<u8> {
+	bytes.iter()
+		.flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+		.collect()
+}
+
+/// combine bits (each 0 or not 0) into bytes, most significant bit first.
+/// the last byte is padded with 0s
+pub fn pack_bits(bits: &[u8]) -> Vec<u8> {
+	bits.chunks(8)
+		.map(|chunk| {
+			chunk.iter()
+				.enumerate()
+				.fold(0, |byte, (i, bit)| byte | map_to(*bit, BIT_MASK[7 - i]))
+		})
+		.collect()
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -113,11 +132,22 @@ mod tests {
 
 	#[test]
 	fn test_bit_mask() {
-		for i in 0..8 {
-			assert_eq!(1 << i, BIT_MASK[i])
+		for (i, mask) in BIT_MASK.into_iter().enumerate() {
+			assert_eq!(1 << i, mask)
 		}
 	}
 
+	#[test]
+	fn test_pack_unpack_bits() {
+		let bytes = [0b10110000, 0xFF, 0x01];
+		let bits = unpack_bits(&bytes);
+
+		assert_eq!(bits[..8], [1, 0, 1, 1, 0, 0, 0, 0]);
+		assert_eq!(bits[16..], [0, 0, 0, 0, 0, 0, 0, 1]);
+		assert_eq!(pack_bits(&bits), bytes);
+		assert_eq!(pack_bits(&[1, 1, 0, 1]), [0b11010000]);
+	}
+
 	fn test_state_to_bit() {
 		for state in 0..4 {
 			for bit in BIT_MASK {
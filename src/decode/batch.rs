@@ -0,0 +1,95 @@
+use super::{Decoder, DecoderState};
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+/// decode many independent packets in parallel on the global rayon thread pool.
+///
+/// `data` is the encoded packets one after another (each `packet_len * 2` bytes long, as output by
+/// [`EncoderState::push_slice`](crate::prelude::EncoderState::push_slice)). the decoded packets are
+/// returned in the same order.
+///
+/// rayon splits the packets into pieces of work and each piece reuses a single [`DecoderState`], so
+/// this is much cheaper than creating a decoder per packet
+pub fn decode_batch(data: &[u8], packet_len: usize) -> Vec<u8> {
+	decode_batch_with(data, packet_len, || DecoderState::new(packet_len))
+}
+
+/// the same as [`decode_batch`], but runs on the given thread pool instead of the global one
+pub fn decode_batch_in(pool: &ThreadPool, data: &[u8], packet_len: usize) -> Vec<u8> {
+	pool.install(|| decode_batch(data, packet_len))
+}
+
+/// the same as [`decode_batch`], but uses decoders created by `new_decoder`, which may be for any code.
+/// each packet is then `packet_len` times the number of outputs of the code long
+pub fn decode_batch_with<D, F>(data: &[u8], packet_len: usize, new_decoder: F) -> Vec<u8>
+where
+	D: Decoder,
+	F: Fn() -> D + Sync + Send,
+{
+	assert!(packet_len > 0);
+
+	let outputs = new_decoder().code().outputs();
+	assert_eq!(data.len() % (packet_len * outputs), 0, "data must be a whole number of packets");
+
+	let mut output = vec![0; data.len() / outputs];
+
+	output.par_chunks_mut(packet_len)
+		.zip(data.par_chunks(packet_len * outputs))
+		.for_each_init(&new_decoder, |decoder, (out, packet)| {
+			decoder.reset();
+			decoder.push_slice(packet);
+			decoder.read_into(out);
+		});
+
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::prelude::*;
+
+	fn packets(count: usize, packet_len: usize) -> (Vec<u8>, Vec<u8>) {
+		packets_with_code(&Code::default(), count, packet_len)
+	}
+
+	fn packets_with_code(code: &Code, count: usize, packet_len: usize) -> (Vec<u8>, Vec<u8>) {
+		let data: Vec<u8> = (0..count * packet_len).map(|x| (x * 31 % 253) as u8).collect();
+
+		let encoded = data.chunks_exact(packet_len)
+			.flat_map(|packet| EncoderState::new(code.clone()).push_slice(packet))
+			.collect();
+
+		(data, encoded)
+	}
+
+	#[test]
+	fn test_decode_batch() {
+		let (data, encoded) = packets(100, 127);
+		assert_eq!(decode_batch(&encoded, 127), data);
+	}
+
+	#[test]
+	fn test_decode_batch_in() {
+		let (data, encoded) = packets(50, 16);
+		let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+
+		assert_eq!(decode_batch_in(&pool, &encoded, 16), data);
+	}
+
+	#[test]
+	fn test_decode_batch_with() {
+		let (data, encoded) = packets(50, 200);
+		assert_eq!(decode_batch_with(&encoded, 200, RcDecoderState::new), data);
+	}
+
+	#[test]
+	fn test_decode_batch_rate_1_3() {
+		let code = Code::new(5, &[0o25, 0o33, 0o37]);
+		let (data, encoded) = packets_with_code(&code, 40, 64);
+
+		assert_eq!(decode_batch_with(&encoded, 64, || DecoderState::with_code(&code, 64)), data);
+		assert_eq!(decode_batch_with(&encoded, 64, || RcDecoderState::with_code(&code)), data);
+	}
+}
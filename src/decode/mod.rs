@@ -1,11 +1,79 @@
+pub mod batch;
 mod single_bit_decode;
 mod single_bit_rc_decode;
+mod single_bit_re_decode;
+mod sequential;
+mod tail_biting;
 
-use single_bit_decode::BitDecoderState;
+pub use single_bit_decode::BitDecoderState;
+pub use single_bit_rc_decode::BitDecoderState as RcBitDecoderState;
+pub use single_bit_re_decode::BitDecoderState as RegisterBitDecoderState;
+pub use sequential::{Erasure, FanoDecoder, StackDecoder};
+pub use tail_biting::decode_tail_biting;
+
+use crate::code::{Code, Trellis};
 use crate::common::*;
 
+use std::sync::Arc;
+
 use rayon::prelude::*;
 
+/// the interface shared by all the decoders, so that they can be swapped for one another
+pub trait Decoder {
+	/// the code being decoded
+	fn code(&self) -> &Code;
+
+	/// push the encoded bytes for one byte of input, one per output of the code
+	/// (as output by [`EncoderState::push_to`](crate::prelude::EncoderState::push_to))
+	fn push(&mut self, bytes: &[u8]);
+
+	/// push a slice of encoded bytes (as output by [`EncoderState::push_slice`](crate::prelude::EncoderState::push_slice)),
+	/// which must be a whole number of sets of outputs long
+	fn push_slice(&mut self, arr: &[u8]) {
+		let outputs = self.code().outputs();
+		check_len(arr.len(), outputs);
+
+		for bytes in arr.chunks_exact(outputs) {
+			self.push(bytes);
+		}
+	}
+
+	/// push the soft symbols for one byte of input: 8 for each encoded byte, one per bit with bit 0 first
+	/// (as output by [`Channel::transmit_soft`](crate::channel::Channel::transmit_soft)).
+	///
+	/// see [`BitDecoderState::push_soft`] for what the soft symbols mean
+	fn push_soft(&mut self, symbols: &[i8]);
+
+	/// push a slice of soft symbols, 8 for each encoded byte, which must be a whole number of sets of
+	/// outputs long
+	fn push_soft_slice(&mut self, arr: &[i8]) {
+		let outputs = self.code().outputs();
+		check_len(arr.len(), outputs * 8);
+
+		for symbols in arr.chunks_exact(outputs * 8) {
+			self.push_soft(symbols);
+		}
+	}
+
+	/// finish decoding and return the decoded bytes
+	fn read(self) -> Vec<u8>;
+
+	/// write the decoded bytes into `arr` without consuming the decoder.
+	///
+	/// `arr` must be exactly as long as the output of [`Decoder::read`] would be.
+	/// call [`Decoder::reset`] afterwards to decode something else
+	fn read_into(&self, arr: &mut [u8]);
+
+	/// the encoder was brought back to state 0 by tail bits at the end (K - 1 zero bytes), so decode the
+	/// path ending in state 0 instead of whichever one is cheapest
+	fn set_terminated(&mut self, terminated: bool);
+
+	/// forget everything that has been pushed so that the decoder can be reused.
+	///
+	/// internal buffers are kept, so reusing a decoder doesn't allocate once it has grown to fit a packet
+	fn reset(&mut self);
+}
+
 #[derive(Debug)]
 pub struct DecoderState {
 	pub decoders: [BitDecoderState; 8]
@@ -13,65 +81,114 @@ pub struct DecoderState {
 
 impl DecoderState {
 	pub fn new(len: usize) -> Self {
+		Self::with_code(&Code::default(), len)
+	}
+
+	/// the same as [`DecoderState::new`], but decodes `code` instead of the default code
+	pub fn with_code(code: &Code, len: usize) -> Self {
+		let trellis = Arc::new(Trellis::new(code));
+
 		Self {
-			decoders: [
-				BitDecoderState::new(len),
-				BitDecoderState::new(len),
-				BitDecoderState::new(len),
-				BitDecoderState::new(len),
-				BitDecoderState::new(len),
-				BitDecoderState::new(len),
-				BitDecoderState::new(len),
-				BitDecoderState::new(len)
-			]
+			decoders: BIT_MASK.map(|_| BitDecoderState::from_trellis(Arc::clone(&trellis), len))
 		}
 	}
 
+	// these forward to `Decoder`, so it doesn't need to be in scope to use them
+
+	/// push the 2 encoded bytes for one byte of input (as output by
+	/// [`EncoderState::push`](crate::prelude::EncoderState::push)).
+	///
+	/// only works for rate 1/2 codes, use [`DecoderState::push_outputs`] for anything else
 	pub fn push(&mut self, byte0: u8, byte1: u8) {
-		for i in 0..8 {
-			self.decoders[i].push(byte0 & BIT_MASK[i], byte1 & BIT_MASK[i])
-		}
+		assert_eq!(self.code().outputs(), 2, "push only works for rate 1/2 codes");
+		Decoder::push(self, &[byte0, byte1])
 	}
 
+	/// see [`Decoder::push`]
+	pub fn push_outputs(&mut self, bytes: &[u8]) {
+		Decoder::push(self, bytes)
+	}
+
+	/// see [`Decoder::push_slice`]
 	pub fn push_slice(&mut self, arr: &[u8]) {
-		let mut i = 0;
+		Decoder::push_slice(self, arr)
+	}
 
-		while i < arr.len() {
-			let byte0 = arr[i];
+	/// see [`Decoder::read`]
+	pub fn read(self) -> Vec<u8> {
+		Decoder::read(self)
+	}
 
-			i += 1;
-			let byte1 = arr[i];
+	pub fn push_slice_para(&mut self, arr: &[u8]) {
+		let outputs = self.code().outputs();
+		check_len(arr.len(), outputs);
 
-			self.push(byte0, byte1);
+		self.decoders.par_iter_mut()
+			.zip(BIT_MASK)
+			.for_each(|(decoder, mask)| {
+				let mut symbols = [0; Code::MAX_OUTPUTS];
 
-			i += 1;
-		}
+				for bytes in arr.chunks_exact(outputs) {
+					decoder.push(lane(bytes, mask, &mut symbols));
+				}
+			})
 	}
+}
 
-	pub fn push_slice_para(&mut self, arr: &[u8]) {
-		self.decoders.par_iter_mut()
-			.enumerate()
-			.for_each(|(b, decoder)| {
-				let mut i = 0;
+/// panic if `len` isn't a whole number of groups of `group` symbols, rather than dropping the rest
+#[inline]
+fn check_len(len: usize, group: usize) {
+	assert!(len.is_multiple_of(group), "{len} symbols isn't a whole number of groups of {group}");
+}
 
-				while i < arr.len() {
-					let bit0 = arr[i] & BIT_MASK[b];
+/// pick out the bits of one lane (`mask`) from a set of encoded bytes
+#[inline]
+fn lane<'a>(bytes: &[u8], mask: u8, symbols: &'a mut [u8; Code::MAX_OUTPUTS]) -> &'a [u8] {
+	for (symbol, byte) in symbols.iter_mut().zip(bytes) {
+		*symbol = byte & mask;
+	}
 
-					i += 1;
-					let bit1 = arr[i] & BIT_MASK[b];
+	&symbols[..bytes.len()]
+}
 
-					decoder.push(bit0, bit1);
+/// pick out the soft symbols of lane `i` from the soft symbols for a set of encoded bytes
+#[inline]
+fn soft_lane<'a>(symbols: &[i8], i: usize, lane_symbols: &'a mut [i8; Code::MAX_OUTPUTS]) -> &'a [i8] {
+	let outputs = symbols.len() / 8;
 
-					i += 1;
-				}
-			})
+	for (j, symbol) in lane_symbols[..outputs].iter_mut().enumerate() {
+		*symbol = symbols[j * 8 + i];
 	}
 
-	pub fn read(mut self) -> Vec<u8> {
+	&lane_symbols[..outputs]
+}
+
+impl Decoder for DecoderState {
+	fn code(&self) -> &Code {
+		self.decoders[0].code()
+	}
+
+	fn push(&mut self, bytes: &[u8]) {
+		let mut symbols = [0; Code::MAX_OUTPUTS];
+
+		for (decoder, mask) in self.decoders.iter_mut().zip(BIT_MASK) {
+			decoder.push(lane(bytes, mask, &mut symbols))
+		}
+	}
+
+	fn push_soft(&mut self, symbols: &[i8]) {
+		let mut lane_symbols = [0; Code::MAX_OUTPUTS];
+
+		for (i, decoder) in self.decoders.iter_mut().enumerate() {
+			decoder.push_soft(soft_lane(symbols, i, &mut lane_symbols))
+		}
+	}
+
+	fn read(mut self) -> Vec<u8> {
 		let mut ans = self.decoders[0].read(BIT_MASK[0]);
 
-		for x in 1..8 {
-			let new = self.decoders[x].read(BIT_MASK[x]);
+		for (decoder, mask) in self.decoders.iter_mut().zip(BIT_MASK).skip(1) {
+			let new = decoder.read(mask);
 
 			debug_assert_eq!(ans.len(), new.len());
 
@@ -82,57 +199,244 @@ impl DecoderState {
 
 		ans
 	}
-}
 
-use single_bit_rc_decode::BitDecoderState as RcDecoder;
+	fn read_into(&self, arr: &mut [u8]) {
+		arr.fill(0);
+
+		for (decoder, mask) in self.decoders.iter().zip(BIT_MASK) {
+			decoder.read_into(mask, arr);
+		}
+	}
+
+	fn set_terminated(&mut self, terminated: bool) {
+		for decoder in &mut self.decoders {
+			decoder.set_terminated(terminated);
+		}
+	}
+
+	fn reset(&mut self) {
+		for decoder in &mut self.decoders {
+			decoder.reset();
+		}
+	}
+}
 
+/// decodes by keeping only the surviving paths through the trellis, so there is no limit on how
+/// much can be pushed before reading.
 #[derive(Debug)]
 pub struct RcDecoderState {
-	pub decoders: [RcDecoder; 8]
+	pub decoders: [RcBitDecoderState; 8]
 }
 
 impl RcDecoderState {
 	pub fn new() -> Self {
+		Self::with_code(&Code::default())
+	}
+
+	/// the same as [`RcDecoderState::new`], but decodes `code` instead of the default code
+	pub fn with_code(code: &Code) -> Self {
+		let trellis = Arc::new(Trellis::new(code));
+
 		Self {
-			decoders: [
-				RcDecoder::new(BIT_MASK[0]),
-				RcDecoder::new(BIT_MASK[1]),
-				RcDecoder::new(BIT_MASK[2]),
-				RcDecoder::new(BIT_MASK[3]),
-				RcDecoder::new(BIT_MASK[4]),
-				RcDecoder::new(BIT_MASK[5]),
-				RcDecoder::new(BIT_MASK[6]),
-				RcDecoder::new(BIT_MASK[7])
-			]
+			decoders: BIT_MASK.map(|mask| RcBitDecoderState::from_trellis(Arc::clone(&trellis), mask))
 		}
 	}
 
+	// these forward to `Decoder`, so it doesn't need to be in scope to use them
+
+	/// push the 2 encoded bytes for one byte of input (as output by
+	/// [`EncoderState::push`](crate::prelude::EncoderState::push)).
+	///
+	/// only works for rate 1/2 codes, use [`RcDecoderState::push_outputs`] for anything else
 	pub fn push(&mut self, byte0: u8, byte1: u8) {
-		for i in 0..8 {
-			self.decoders[i].push(byte0 & BIT_MASK[i], byte1 & BIT_MASK[i])
-		}
+		assert_eq!(self.code().outputs(), 2, "push only works for rate 1/2 codes");
+		Decoder::push(self, &[byte0, byte1])
+	}
+
+	/// see [`Decoder::push`]
+	pub fn push_outputs(&mut self, bytes: &[u8]) {
+		Decoder::push(self, bytes)
 	}
 
+	/// see [`Decoder::push_slice`]
 	pub fn push_slice(&mut self, arr: &[u8]) {
-		let mut i = 0;
+		Decoder::push_slice(self, arr)
+	}
+
+	/// see [`Decoder::read`]
+	pub fn read(self) -> Vec<u8> {
+		Decoder::read(self)
+	}
+
+	/// create a decoder that makes bytes available as soon as every survivor path agrees on them,
+	/// see [`RcDecoderState::settled`]
+	pub fn with_early_output() -> Self {
+		Self::new().early_output()
+	}
 
-		while i < arr.len() {
-			let byte0 = arr[i];
+	/// make bytes available as soon as every survivor path agrees on them, see [`RcDecoderState::settled`]
+	pub fn early_output(mut self) -> Self {
+		for decoder in &mut self.decoders {
+			decoder.set_early_output(true);
+		}
 
-			i += 1;
-			let byte1 = arr[i];
+		self
+	}
 
-			self.push(byte0, byte1);
+	/// takes the bytes that have been settled so far (only ever yields anything when created using
+	/// [`RcDecoderState::with_early_output`]).
+	///
+	/// a byte is settled once every survivor path goes through the same bits for it, so no later
+	/// input can change it. bytes taken here won't be returned by [`Decoder::read`]
+	pub fn settled(&mut self) -> impl Iterator<Item = u8> + '_ {
+		std::iter::from_fn(|| {
+			if !self.decoders.iter().all(|decoder| decoder.has_settled()) {
+				return None;
+			}
 
-			i += 1;
+			Some(self.decoders.iter_mut()
+				.map(|decoder| decoder.pop_settled().unwrap())
+				.fold(0, |byte, bit| byte | bit))
+		})
+	}
+
+	/// the same as [`Decoder::push_slice`], but each bit lane is decoded on its own thread
+	pub fn push_slice_para(&mut self, arr: &[u8]) {
+		let outputs = self.code().outputs();
+		check_len(arr.len(), outputs);
+
+		self.decoders.par_iter_mut()
+			.zip(BIT_MASK)
+			.for_each(|(decoder, mask)| {
+				let mut symbols = [0; Code::MAX_OUTPUTS];
+
+				for bytes in arr.chunks_exact(outputs) {
+					decoder.push(lane(bytes, mask, &mut symbols));
+				}
+			})
+	}
+}
+
+impl Default for RcDecoderState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Decoder for RcDecoderState {
+	fn code(&self) -> &Code {
+		self.decoders[0].code()
+	}
+
+	fn push(&mut self, bytes: &[u8]) {
+		let mut symbols = [0; Code::MAX_OUTPUTS];
+
+		for (decoder, mask) in self.decoders.iter_mut().zip(BIT_MASK) {
+			decoder.push(lane(bytes, mask, &mut symbols))
 		}
 	}
 
-	pub fn read(self) -> Vec<u8> {
+	fn push_soft(&mut self, symbols: &[i8]) {
+		let mut lane_symbols = [0; Code::MAX_OUTPUTS];
+
+		for (i, decoder) in self.decoders.iter_mut().enumerate() {
+			decoder.push_soft(soft_lane(symbols, i, &mut lane_symbols))
+		}
+	}
+
+	fn read(self) -> Vec<u8> {
+		let mut ans = self.decoders[0].read();
+
+		for decoder in &self.decoders[1..] {
+			let new = decoder.read();
+
+			debug_assert_eq!(ans.len(), new.len());
+
+			for i in 0..ans.len() {
+				ans[i] |= new[i];
+			}
+		}
+
+		ans
+	}
+
+	fn read_into(&self, arr: &mut [u8]) {
+		arr.fill(0);
+
+		for decoder in &self.decoders {
+			decoder.read_into(arr);
+		}
+	}
+
+	fn set_terminated(&mut self, terminated: bool) {
+		for decoder in &mut self.decoders {
+			decoder.set_terminated(terminated);
+		}
+	}
+
+	fn reset(&mut self) {
+		for decoder in &mut self.decoders {
+			decoder.reset();
+		}
+	}
+}
+
+/// decodes using register exchange instead of traceback.
+///
+/// decoded bits are held in a fixed size register per state, so memory use doesn't grow with the
+/// length of the stream and older bits are output while pushing.
+#[derive(Debug)]
+pub struct RegisterDecoderState {
+	pub decoders: [RegisterBitDecoderState; 8]
+}
+
+impl RegisterDecoderState {
+	pub fn new() -> Self {
+		Self::with_code(&Code::default())
+	}
+
+	/// the same as [`RegisterDecoderState::new`], but decodes `code` instead of the default code
+	pub fn with_code(code: &Code) -> Self {
+		let trellis = Arc::new(Trellis::new(code));
+
+		Self {
+			decoders: BIT_MASK.map(|mask| RegisterBitDecoderState::from_trellis(Arc::clone(&trellis), mask))
+		}
+	}
+}
+
+impl Default for RegisterDecoderState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Decoder for RegisterDecoderState {
+	fn code(&self) -> &Code {
+		self.decoders[0].code()
+	}
+
+	fn push(&mut self, bytes: &[u8]) {
+		let mut symbols = [0; Code::MAX_OUTPUTS];
+
+		for (decoder, mask) in self.decoders.iter_mut().zip(BIT_MASK) {
+			decoder.push(lane(bytes, mask, &mut symbols))
+		}
+	}
+
+	fn push_soft(&mut self, symbols: &[i8]) {
+		let mut lane_symbols = [0; Code::MAX_OUTPUTS];
+
+		for (i, decoder) in self.decoders.iter_mut().enumerate() {
+			decoder.push_soft(soft_lane(symbols, i, &mut lane_symbols))
+		}
+	}
+
+	fn read(self) -> Vec<u8> {
 		let mut ans = self.decoders[0].read();
 
-		for x in 1..8 {
-			let new = self.decoders[x].read();
+		for decoder in &self.decoders[1..] {
+			let new = decoder.read();
 
 			debug_assert_eq!(ans.len(), new.len());
 
@@ -143,4 +447,24 @@ impl RcDecoderState {
 
 		ans
 	}
+
+	fn read_into(&self, arr: &mut [u8]) {
+		arr.fill(0);
+
+		for decoder in &self.decoders {
+			decoder.read_into(arr);
+		}
+	}
+
+	fn set_terminated(&mut self, terminated: bool) {
+		for decoder in &mut self.decoders {
+			decoder.set_terminated(terminated);
+		}
+	}
+
+	fn reset(&mut self) {
+		for decoder in &mut self.decoders {
+			decoder.reset();
+		}
+	}
 }
@@ -0,0 +1,522 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use crate::code::{self, Code};
+
+/// how many steps of the metric make up one bit of information
+const METRIC_SCALE: f64 = 64.0;
+
+/// the default for how likely a received symbol is to be wrong, see [`FanoDecoder::with_crossover`]
+const DEFAULT_CROSSOVER: f64 = 0.03;
+
+/// the default for how many nodes can be visited before giving up
+const DEFAULT_BUDGET: usize = 1_000_000;
+
+/// a sequential decoder ran out of its budget of node visits before reaching the end of the block.
+///
+/// the block is too badly damaged to decode in reasonable time, so should be treated as erased
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Erasure {
+	/// how many nodes were visited
+	pub visits: usize,
+	/// the furthest into the block the decoder got
+	pub depth: usize,
+}
+
+impl fmt::Display for Erasure {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "gave up after visiting {} nodes, at most {} bits in", self.visits, self.depth)
+	}
+}
+
+impl std::error::Error for Erasure {}
+
+/// the fano metric for each soft symbol, and the code, shared by the sequential decoders.
+///
+/// a soft symbol is treated as a log likelihood ratio, scaled so that one as confident as it can be is
+/// wrong with probability `crossover`. the metric of a branch is then `log2(P(symbol | expected) / P(symbol)) - R`
+/// summed over its symbols, which goes up along the right path and down along the wrong ones
+#[derive(Debug, Clone)]
+struct Metric {
+	code: Code,
+	/// indexed by `expected << 8 | symbol as u8`, in 64ths of a bit
+	table: Vec<i32>,
+}
+
+impl Metric {
+	fn new(code: &Code, crossover: f64) -> Self {
+		assert!(crossover > 0.0 && crossover < 0.5, "the crossover probability must be between 0 and 0.5");
+
+		let rate = 1.0 / code.outputs() as f64;
+		let confidence = ((1.0 - crossover) / crossover).ln();
+
+		let table = (0..512)
+			.map(|i| {
+				let expected = i >> 8 != 0;
+				let symbol = (i & 0xFF) as u8 as i8;
+
+				// the probability the symbol was sent as a 1
+				let one = 1.0 / (1.0 + (-confidence * symbol as f64 / 127.0).exp());
+				let likelihood = if expected { one } else { 1.0 - one };
+
+				(METRIC_SCALE * (1.0 + likelihood.log2() - rate)).round() as i32
+			})
+			.collect();
+
+		Self {
+			code: code.clone(),
+			table,
+		}
+	}
+
+	/// the metric of the branch for `bit` from `state`, given the symbols received for it
+	fn branch(&self, state: u32, bit: u8, symbols: &[i8]) -> i32 {
+		let expected = self.code.output(state, bit);
+
+		symbols.iter()
+			.enumerate()
+			.map(|(j, symbol)| self.table[((expected as usize >> j) & 1) << 8 | *symbol as u8 as usize])
+			.sum()
+	}
+
+	/// the number of bits in a block of symbols, and where the tail starts (if there is one)
+	fn block_len(&self, symbols: &[i8], tail: bool) -> (usize, usize) {
+		let outputs = self.code.outputs();
+		assert_eq!(symbols.len() % outputs, 0, "symbols must be a whole number of bits");
+
+		let len = symbols.len() / outputs;
+		let tail_len = if tail { self.code.constraint_len() as usize - 1 } else { 0 };
+		assert!(len > tail_len, "the block must be longer than its tail");
+
+		(len, len - tail_len)
+	}
+
+	fn symbols<'a>(&self, symbols: &'a [i8], depth: usize) -> &'a [i8] {
+		let outputs = self.code.outputs();
+		&symbols[depth * outputs..(depth + 1) * outputs]
+	}
+}
+
+fn to_soft(symbols: &[u8]) -> Vec<i8> {
+	symbols.iter().map(|symbol| code::to_soft(*symbol)).collect()
+}
+
+/// decodes with the fano algorithm: a depth first search through the code tree that moves forward while
+/// the path metric stays above a threshold, and backs up to try other branches (loosening the threshold
+/// if that doesn't help) when it doesn't.
+///
+/// unlike viterbi decoding, the work done doesn't grow with the number of states, so it works for any
+/// constraint length. it does grow quickly with the number of errors, so a budget of node visits is
+/// given, and blocks that need more than that are reported as [`Erasure`]s
+#[derive(Debug, Clone)]
+pub struct FanoDecoder {
+	metric: Metric,
+	/// how much the threshold moves by, in the same units as the metric
+	delta: i32,
+	budget: usize,
+	tail: bool,
+}
+
+impl FanoDecoder {
+	pub fn new(code: &Code) -> Self {
+		Self {
+			metric: Metric::new(code, DEFAULT_CROSSOVER),
+			delta: 2 * METRIC_SCALE as i32,
+			budget: DEFAULT_BUDGET,
+			tail: false,
+		}
+	}
+
+	/// how likely a symbol received with full confidence (or a hard symbol) is to be wrong.
+	/// the default is 0.03, and it should be roughly how bad the channel is
+	pub fn with_crossover(mut self, crossover: f64) -> Self {
+		self.metric = Metric::new(&self.metric.code, crossover);
+		self
+	}
+
+	/// how much the threshold moves by, in bits (the default is 2). smaller steps search more carefully
+	/// but more slowly
+	pub fn with_delta(mut self, delta: f64) -> Self {
+		assert!(delta > 0.0);
+
+		self.delta = (delta * METRIC_SCALE).round() as i32;
+		self
+	}
+
+	/// the most nodes that can be visited while decoding a block, before giving up on it
+	pub fn with_budget(mut self, max_visits: usize) -> Self {
+		self.budget = max_visits;
+		self
+	}
+
+	/// the block ends with K - 1 0s to bring the encoder back to state 0, which the decoder can make use of.
+	/// the tail bits are still returned when decoding
+	pub fn with_tail(mut self) -> Self {
+		self.tail = true;
+		self
+	}
+
+	pub fn code(&self) -> &Code {
+		&self.metric.code
+	}
+
+	/// decode hard symbols (each 0 or not 0), one per output of the code for each bit one after another
+	pub fn decode_hard(&self, symbols: &[u8]) -> Result<Vec<u8>, Erasure> {
+		self.decode(&to_soft(symbols))
+	}
+
+	/// decode soft symbols, one per output of the code for each bit one after another, returning the bits
+	/// (each 0 or 1).
+	///
+	/// see [`BitDecoderState::push_soft`](super::BitDecoderState::push_soft) for what the soft symbols mean
+	pub fn decode(&self, symbols: &[i8]) -> Result<Vec<u8>, Erasure> {
+		let (len, tail) = self.metric.block_len(symbols, self.tail);
+
+		let mut nodes = vec![FanoNode::default(); len + 1];
+		nodes[0].branches(&self.metric, symbols, 0, tail);
+
+		let mut depth = 0;
+		let mut furthest = 0;
+		let mut threshold = 0;
+
+		for _ in 0..self.budget {
+			let node = &nodes[depth];
+			let metric = node.metric + node.branch_metrics[node.tried];
+
+			if metric >= threshold {
+				// look forward
+				if node.metric < threshold + self.delta {
+					// the first time here, so tighten the threshold
+					while metric >= threshold + self.delta {
+						threshold += self.delta;
+					}
+				}
+
+				let state = self.metric.code.next_state(node.state, node.bits[node.tried]);
+
+				depth += 1;
+				furthest = furthest.max(depth);
+
+				if depth == len {
+					return Ok(nodes[1..].iter().map(|node| node.input).collect());
+				}
+
+				let next = &mut nodes[depth];
+				next.metric = metric;
+				next.state = state;
+				next.input = state as u8 & 1;
+				next.branches(&self.metric, symbols, depth, tail);
+
+				continue;
+			}
+
+			// look back
+			loop {
+				if depth == 0 || nodes[depth - 1].metric < threshold {
+					// can't go back either, so loosen the threshold and start again from the best branch
+					threshold -= self.delta;
+					nodes[depth].tried = 0;
+					break;
+				}
+
+				depth -= 1;
+
+				let node = &mut nodes[depth];
+				if node.tried == 0 && node.branch_count == 2 {
+					// try the other branch
+					node.tried = 1;
+					break;
+				}
+			}
+		}
+
+		Err(Erasure {
+			visits: self.budget,
+			depth: furthest,
+		})
+	}
+}
+
+/// a node on the path the fano decoder is currently following
+#[derive(Debug, Clone, Default)]
+struct FanoNode {
+	/// the path metric up to this node
+	metric: i32,
+	/// the encoder state at this node
+	state: u32,
+	/// the bit input to get here
+	input: u8,
+	/// the branches out of this node, best first
+	bits: [u8; 2],
+	branch_metrics: [i32; 2],
+	branch_count: usize,
+	/// which branch is being followed
+	tried: usize,
+}
+
+impl FanoNode {
+	/// work out the branches out of this node, which is `depth` bits in. in the tail only 0s are input
+	fn branches(&mut self, metric: &Metric, symbols: &[i8], depth: usize, tail: usize) {
+		let symbols = metric.symbols(symbols, depth);
+		let zero = metric.branch(self.state, 0, symbols);
+
+		self.tried = 0;
+
+		if depth >= tail {
+			self.bits = [0, 1];
+			self.branch_metrics = [zero, i32::MIN];
+			self.branch_count = 1;
+			return;
+		}
+
+		let one = metric.branch(self.state, 1, symbols);
+
+		if one > zero {
+			self.bits = [1, 0];
+			self.branch_metrics = [one, zero];
+		} else {
+			self.bits = [0, 1];
+			self.branch_metrics = [zero, one];
+		}
+
+		self.branch_count = 2;
+	}
+}
+
+/// decodes with the stack algorithm: keeps every path explored so far sorted by its metric, and always
+/// extends the best one, until a path reaches the end of the block.
+///
+/// it visits fewer nodes than [`FanoDecoder`] but needs memory for every path explored. like it, it works
+/// for any constraint length and reports blocks that need more than the budget of node visits as [`Erasure`]s
+#[derive(Debug, Clone)]
+pub struct StackDecoder {
+	metric: Metric,
+	budget: usize,
+	tail: bool,
+}
+
+impl StackDecoder {
+	pub fn new(code: &Code) -> Self {
+		Self {
+			metric: Metric::new(code, DEFAULT_CROSSOVER),
+			budget: DEFAULT_BUDGET,
+			tail: false,
+		}
+	}
+
+	/// see [`FanoDecoder::with_crossover`]
+	pub fn with_crossover(mut self, crossover: f64) -> Self {
+		self.metric = Metric::new(&self.metric.code, crossover);
+		self
+	}
+
+	/// the most nodes that can be visited (paths extended) while decoding a block, before giving up on it
+	pub fn with_budget(mut self, max_visits: usize) -> Self {
+		self.budget = max_visits;
+		self
+	}
+
+	/// see [`FanoDecoder::with_tail`]
+	pub fn with_tail(mut self) -> Self {
+		self.tail = true;
+		self
+	}
+
+	pub fn code(&self) -> &Code {
+		&self.metric.code
+	}
+
+	/// see [`FanoDecoder::decode_hard`]
+	pub fn decode_hard(&self, symbols: &[u8]) -> Result<Vec<u8>, Erasure> {
+		self.decode(&to_soft(symbols))
+	}
+
+	/// see [`FanoDecoder::decode`]
+	pub fn decode(&self, symbols: &[i8]) -> Result<Vec<u8>, Erasure> {
+		let (len, tail) = self.metric.block_len(symbols, self.tail);
+
+		// every path explored, as the node it ends at and the one before it
+		let mut arena: Vec<(u32, u8)> = Vec::new();
+		let mut stack = BinaryHeap::new();
+
+		stack.push(StackEntry {
+			metric: 0,
+			depth: 0,
+			state: 0,
+			node: u32::MAX,
+		});
+
+		let mut furthest = 0;
+
+		for _ in 0..self.budget {
+			let entry = stack.pop().unwrap();
+
+			if entry.depth == len {
+				let mut bits = vec![0; len];
+				let mut node = entry.node;
+
+				for bit in bits.iter_mut().rev() {
+					let (prev, input) = arena[node as usize];
+					*bit = input;
+					node = prev;
+				}
+
+				return Ok(bits);
+			}
+
+			let symbols = self.metric.symbols(symbols, entry.depth);
+			let inputs: &[u8] = if entry.depth >= tail { &[0] } else { &[0, 1] };
+
+			for bit in inputs {
+				arena.push((entry.node, *bit));
+
+				stack.push(StackEntry {
+					metric: entry.metric + self.metric.branch(entry.state, *bit, symbols),
+					depth: entry.depth + 1,
+					state: self.metric.code.next_state(entry.state, *bit),
+					node: arena.len() as u32 - 1,
+				});
+			}
+
+			furthest = furthest.max(entry.depth + 1);
+		}
+
+		Err(Erasure {
+			visits: self.budget,
+			depth: furthest,
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StackEntry {
+	metric: i32,
+	depth: usize,
+	state: u32,
+	/// where the path ends in the arena
+	node: u32,
+}
+
+impl Ord for StackEntry {
+	/// the best path is the one with the highest metric, going with the longest to break ties
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.metric.cmp(&other.metric)
+			.then(self.depth.cmp(&other.depth))
+	}
+}
+
+impl PartialOrd for StackEntry {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::encode::EncoderState;
+
+	/// a K=32 code, far too big for viterbi decoding
+	fn long_code() -> Code {
+		Code::new(32, &[0xF2D05351, 0xE4613C47])
+	}
+
+	fn encode(code: &Code, bits: &[u8]) -> Vec<u8> {
+		let bits: Vec<bool> = bits.iter().map(|bit| *bit != 0).collect();
+
+		EncoderState::<bool>::new(code.clone())
+			.push_slice(&bits)
+			.into_iter()
+			.map(u8::from)
+			.collect()
+	}
+
+	/// some bits followed by the K - 1 tail bits
+	fn bits(code: &Code, len: usize) -> Vec<u8> {
+		let mut bits: Vec<u8> = (0..len).map(|i| ((i * 7 + i / 3) % 5 % 2) as u8).collect();
+		bits.resize(len + code.constraint_len() as usize - 1, 0);
+		bits
+	}
+
+	#[test]
+	fn test_metric() {
+		let metric = Metric::new(&Code::default(), 0.03);
+
+		// sure and right goes up by a bit less than 1 - R, sure and wrong goes down a lot, and an erasure
+		// costs R
+		assert_eq!(metric.table[1 << 8 | 127], 29);
+		assert_eq!(metric.table[127], -292);
+		assert_eq!(metric.table[1 << 8], -32);
+		assert_eq!(metric.table[0], -32);
+	}
+
+	#[test]
+	fn test_fano() {
+		let code = long_code();
+		let bits = bits(&code, 200);
+
+		let mut symbols = encode(&code, &bits);
+		for i in (5..symbols.len()).step_by(23) {
+			symbols[i] ^= 1;
+		}
+
+		let decoder = FanoDecoder::new(&code).with_tail();
+		assert_eq!(decoder.decode_hard(&symbols), Ok(bits));
+	}
+
+	#[test]
+	fn test_stack() {
+		let code = long_code();
+		let bits = bits(&code, 200);
+
+		let mut symbols = encode(&code, &bits);
+		for i in (5..symbols.len()).step_by(23) {
+			symbols[i] ^= 1;
+		}
+
+		let decoder = StackDecoder::new(&code).with_tail();
+		assert_eq!(decoder.decode_hard(&symbols), Ok(bits));
+	}
+
+	#[test]
+	fn test_soft() {
+		let code = Code::new(7, &[0o171, 0o133]);
+		let bits = bits(&code, 100);
+
+		let mut symbols: Vec<i8> = encode(&code, &bits).into_iter().map(code::to_soft).collect();
+
+		// not very sure, and some wrong but not by much
+		for (i, symbol) in symbols.iter_mut().enumerate() {
+			*symbol /= 2;
+
+			if i % 7 == 3 {
+				*symbol = -*symbol / 4;
+			}
+		}
+
+		assert_eq!(FanoDecoder::new(&code).with_tail().decode(&symbols), Ok(bits.clone()));
+		assert_eq!(StackDecoder::new(&code).with_tail().decode(&symbols), Ok(bits));
+	}
+
+	#[test]
+	fn test_budget() {
+		let code = long_code();
+		let bits = bits(&code, 200);
+
+		let mut symbols = encode(&code, &bits);
+
+		// a burst of errors too bad to get through
+		for symbol in &mut symbols[100..160] {
+			*symbol ^= 1;
+		}
+
+		let erasure = FanoDecoder::new(&code).with_tail().with_budget(5000).decode_hard(&symbols).unwrap_err();
+		assert_eq!(erasure.visits, 5000);
+		assert!(erasure.depth < bits.len());
+
+		let erasure = StackDecoder::new(&code).with_tail().with_budget(5000).decode_hard(&symbols).unwrap_err();
+		assert_eq!(erasure.visits, 5000);
+		assert!(erasure.depth < bits.len());
+	}
+}
@@ -1,239 +1,301 @@
-use crate::common::*;
-use crate::encode::EncoderState;
+use std::sync::Arc;
 
-#[derive(Debug)]
+use crate::code::{Code, Trellis};
+
+#[derive(Debug, Clone)]
 pub struct BitDecoderState {
-	trellis: Vec<[Link; 4]>,
+	/// which way each state was reached at each step, one bit per state (see [`Trellis::acs_decisions`]).
+	///
+	/// this is all that is needed to trace back, so even K=15 codes only take 2KiB per bit pushed
+	decisions: Vec<u64>,
+	code: Arc<Trellis>,
+	/// the cost of the best path to each state
+	costs: Vec<u32>,
+	/// scratch space for the next step's costs
+	new_costs: Vec<u32>,
+	/// scratch space for the branch metrics of the current push
+	metrics: Vec<u32>,
+	len: usize,
+	/// how much has been taken away from every cost to stop them overflowing (see [`Trellis::normalize`])
+	normalized: u64,
+	/// the encoder could have started in any state, instead of state 0
+	any_start: bool,
+	/// the encoder was brought back to state 0 at the end, so the traceback starts there
+	terminated: bool,
 }
 
 impl BitDecoderState {
 	/// entering the right capacity will prevent any additional memory allocations while pushing bits
 	/// into the decoder
 	pub fn new(capacity: usize) -> Self {
-		assert!(capacity <= 127);
+		Self::with_code(&Code::default(), capacity)
+	}
+
+	/// the same as [`BitDecoderState::new`], but decodes `code` instead of the default code
+	pub fn with_code(code: &Code, capacity: usize) -> Self {
+		Self::from_trellis(Arc::new(Trellis::new(code)), capacity)
+	}
+
+	pub(crate) fn from_trellis(code: Arc<Trellis>, capacity: usize) -> Self {
 		assert!(capacity >= 2); // idk if this is needed
 
 		Self {
-			trellis: Vec::with_capacity(capacity),
+			decisions: Vec::with_capacity(capacity * Self::words(&code)),
+			costs: code.start_costs(),
+			new_costs: vec![0; code.states()],
+			metrics: vec![0; code.symbols()],
+			len: 0,
+			normalized: 0,
+			code,
+			any_start: false,
+			terminated: false,
 		}
 	}
 
-	/// push a pair of bits to be decoded
+	/// don't assume the encoder started in state 0, as is the case for tail biting codes
+	pub fn set_any_start(&mut self, any_start: bool) {
+		self.any_start = any_start;
+	}
+
+	/// the encoder was brought back to state 0 by tail bits at the end, so trace back from state 0
+	/// instead of from whichever state is cheapest
+	pub fn set_terminated(&mut self, terminated: bool) {
+		self.terminated = terminated;
+	}
+
+	pub fn code(&self) -> &Code {
+		self.code.code()
+	}
+
+	/// push the symbols output by the encoder for a single bit (one per output of the code)
 	///
 	/// takes u8s instead of bools for conveince (just do a `bitwise and` between the mask and the byte)
-	pub fn push(&mut self, s0: u8, s1: u8) {
-		if self.len() >= 127 {
-			panic!("TOO MANY BITS BEING DECODED BEFORE RESET")
-		}
-
-		let bit_pair = combine(s0, s1);
+	pub fn push(&mut self, symbols: &[u8]) {
+		self.code.hard_metrics(symbols, &mut self.metrics);
+		self.step();
+	}
 
-		self.add_column();
+	/// push the soft symbols received for a single bit (one per output of the code).
+	///
+	/// positive means 1 and negative means 0, with 0 meaning nothing is known (like for punctured symbols)
+	pub fn push_soft(&mut self, symbols: &[i8]) {
+		self.code.soft_metrics(symbols, &mut self.metrics);
+		self.step();
+	}
 
-		for state in self.states() {
-			for (link, pos) in Link::next(state, bit_pair, self.prev_cost(state)) {
-				self.add_link(link, pos);
+	/// move forward one step using the branch metrics in `self.metrics`
+	fn step(&mut self) {
+		if self.len == 0 {
+			// the encoder starts in state 0, unless told otherwise
+			if self.any_start {
+				self.costs.fill(0);
+			} else {
+				Trellis::reset_costs(&mut self.costs);
 			}
 		}
-	}
 
-	fn add_column(&mut self) {
-		self.trellis.push([Link::NONE; 4]);
+		let start = self.decisions.len();
+		self.decisions.resize(start + Self::words(&self.code), 0);
+
+		self.code.acs_decisions(&self.metrics, &self.costs, &mut self.new_costs, &mut self.decisions[start..]);
+		std::mem::swap(&mut self.costs, &mut self.new_costs);
+		self.normalized += Trellis::normalize(&mut self.costs) as u64;
+
+		self.len += 1;
 	}
 
-	fn states(&self) -> Vec<u8> {
-		match self.len() {
-			0 => unreachable!(), // should only be called after adding first column to vec
-			1 => vec![0],
-			2 => vec![0, 1],
-			_ => vec![0, 1, 2, 3]
-		}
+	/// the number of words of decisions per step
+	fn words(code: &Trellis) -> usize {
+		code.states().div_ceil(64)
 	}
 
 	/// ouputs a vector of u8s where only the correct bits are set to 1
 	pub fn read(&mut self, bit: u8) -> Vec<u8> {
+		let mut ans = vec![0; self.len()];
+		self.read_into(bit, &mut ans);
+		self.reset();
+
+		ans
+	}
+
+	/// sets the correct bits of `arr` to 1, leaving the others alone.
+	///
+	/// `arr` must be exactly as long as the number of bits pushed
+	pub fn read_into(&self, bit: u8, arr: &mut [u8]) {
 		// TODO: figure out what to do if the decoder hasn't been given enough data
 		// for now, just assert that it has
 		assert!(self.len() > 1);
+		assert_eq!(arr.len(), self.len());
 
-		let mut ans = Vec::with_capacity(self.len());
-
-		// find the link to start from
+		// find the state to start from
 		let mut pos = self.find_start_pos();
 
-		// follow the links to the start and record what bit we think was encoded
-		while !self.trellis.is_empty() {
-			// record the bit
-			ans.push(state_to_bit(pos, bit));
-
-			// get position of next link
-			pos = self.get_last_link(pos).prev_state;
+		// follow the decisions to the start and record what bit we think was encoded
+		for i in (0..self.len()).rev() {
+			if pos & 1 != 0 {
+				arr[i] |= bit;
+			}
 
-			// ditch the current column, thus moving onto next column
-			self.trellis.pop().unwrap();
+			pos = self.prev_state(i, pos);
 		}
-
-		ans.reverse(); // TODO: fill array backwards instead of reversing
-
-		ans
-	}
-
-	fn len(&self) -> usize {
-		self.trellis.len()
 	}
 
-	fn get_link(&self, index: usize, pos: u8) -> Link {
-		self.trellis[index][pos as usize].clone()
+	/// forget everything that has been pushed, keeping the memory that was allocated for it
+	pub fn reset(&mut self) {
+		self.decisions.clear();
+		self.len = 0;
+		self.normalized = 0;
 	}
 
-	fn get_last_link(&self, pos: u8) -> Link {
-		self.trellis.last().unwrap()[pos as usize].clone()
+	pub fn len(&self) -> usize {
+		self.len
 	}
 
-	fn add_link(&mut self, new_link: Link, pos: u8) {
-		self.trellis
-			.last_mut()
-			.unwrap()[pos as usize]
-			.minimize_cost(new_link);
-	}
-
-	fn find_start_pos(&self) -> u8 {
-		self.trellis
-			.last().unwrap()
-			.iter().enumerate().min_by_key(|(_, link)| link.cost)
-			.unwrap().0 as u8
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
 	}
 
+	/// the cost of the best path through the trellis so far.
+	///
+	/// for hard symbols this is the number of symbols it disagrees with, and for soft symbols it is the sum
+	/// of how far each symbol is from what the path expected (an erasure always costs 127)
+	pub fn path_metric(&self) -> u64 {
+		assert!(!self.is_empty());
 
-	fn prev_cost(&self, pos: u8) -> u8 {
-		match self.len() {
-			0 => unreachable!(), // should only be called after adding first column to vec
-			1 => 0, // there is no previous link
-			_ => {
-				self.get_link(self.len() - 2, pos).cost
-			}
-		}
+		self.normalized + self.costs[self.find_start_pos()] as u64
 	}
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct Link {
-	pub prev_state: u8,
-
-	/// enough for the decoder to consume 254 bits (yeilding 127 bits) no matter what
-	pub cost: u8,
-}
 
-impl Link {
-	pub const NONE: Self = Link {
-		prev_state: 255,
-		cost: 255,
-	};
+	/// the state that `state` was reached from at step `index`
+	fn prev_state(&self, index: usize, state: usize) -> usize {
+		let word = self.decisions[index * Self::words(&self.code) + state / 64];
+		let upper = (word >> (state % 64)) & 1;
 
-	/// return the next 2 links and where the link should be placed
-	pub fn next(state: u8, bit_pair: u8, prev_cost: u8) -> [(Self, u8); 2] {
-		[
-			Self::generate(state, bit_pair, prev_cost, 0),
-			Self::generate(state, bit_pair, prev_cost, 1)
-		]
+		(state >> 1) | (upper as usize * self.code.states() / 2)
 	}
 
-	pub fn minimize_cost(&mut self, other: Self) {
-		// TODO: figure out what should be done if the costs are the same...
-		if self.cost > other.cost {
-			*self = other;
+	fn find_start_pos(&self) -> usize {
+		if self.terminated {
+			return 0;
 		}
-	}
-
-	fn generate(state: u8, bit_pair: u8, prev_cost: u8, bit: u8)  -> (Self, u8) {
-		/* NOTES to self
-		* the prev_state for each link is simply the state parameter
-		* hamming dist is between bit_pair and what comes out of the encoder.input_byte_out function
-		* the correct placement for each link is the internal state of its encoder after inputting the 1 or 0
-		 */
-
-		let mut encoder: EncoderState<u8> = state.into();
-		let hypothetical_bit_pair = encoder.push_return_bitpair(stretch(bit));
-
-		(
-			Self {
-				prev_state: state,
-				cost: prev_cost + Self::hamming_dist(bit_pair, hypothetical_bit_pair)
-			},
-			encoder.into()
-		)
-	}
 
-	fn hamming_dist(a: u8, b: u8) -> u8 {
-		(a ^ b).count_ones() as u8
+		self.costs.iter()
+			.enumerate()
+			.min_by_key(|(_, cost)| **cost)
+			.unwrap().0
 	}
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::encode::EncoderState;
+
+	fn encode(code: &Code, bits: &[u8]) -> Vec<u8> {
+		let mut encoder = EncoderState::<u8>::new(code.clone());
+		encoder.push_slice(bits)
+	}
 
 	#[test]
-	fn test_hamming_distance() {
-		assert_eq!(Link::hamming_dist(255, 0), 8);
-		assert_eq!(Link::hamming_dist(1, 0), 1);
-		assert_eq!(Link::hamming_dist(2, 0), 1);
-		assert_eq!(Link::hamming_dist(2, 1), 2);
-		assert_eq!(Link::hamming_dist(0, 0), 0);
-		assert_eq!(Link::hamming_dist(255, 255), 0);
-		assert_eq!(Link::hamming_dist(0b00010101, 0b00000100), 2);
+	fn test_first_column() {
+		let mut decoder = BitDecoderState::new(2);
+		decoder.push(&[1, 1]);
+
+		// only states 0 and 1 can be reached from state 0
+		assert_eq!(decoder.costs, [2, 0, Trellis::UNREACHED, Trellis::UNREACHED]);
+		assert_eq!(decoder.prev_state(0, 0), 0);
+		assert_eq!(decoder.prev_state(0, 1), 0);
 	}
 
 	#[test]
-	fn test_minimize_cost() {
-		let mut link_a = Link {
-			prev_state: 0,
-			cost: 10
-		};
+	fn test_k15() {
+		// a K=15 rate 1/4 code, with 16384 states
+		let code = Code::new(15, &[0o46321, 0o51271, 0o63667, 0o70535]);
+		let bits: Vec<u8> = (0..300).map(|i| ((i * 11 + i / 7) % 3 % 2) as u8).collect();
+
+		let mut encoded = encode(&code, &bits);
+		for i in (7..encoded.len()).step_by(50) {
+			encoded[i] ^= 1;
+		}
 
-		let link_b = Link {
-			prev_state: 1,
-			cost: 11
-		};
+		let mut decoder = BitDecoderState::with_code(&code, bits.len());
+		for symbols in encoded.chunks_exact(4) {
+			decoder.push(symbols);
+		}
 
-		link_a.minimize_cost(link_b);
+		// 2KiB of decisions per bit, not 128KiB of links
+		assert_eq!(decoder.decisions.len(), bits.len() * 256);
+		assert_eq!(decoder.read(1), bits);
+	}
 
-		assert_eq!(link_a.prev_state, 0);
-		assert_eq!(link_a.cost, 10);
+	#[test]
+	fn test_corrects_errors() {
+		let code = Code::new(5, &[0o23, 0o35]);
+		let bits: Vec<u8> = (0..100).map(|i| ((i * 5) % 7 % 2) as u8).collect();
 
-		let link_c = Link {
-			prev_state: 2,
-			cost: 9,
-		};
+		let mut encoded = encode(&code, &bits);
+		encoded[20] ^= 1;
+		encoded[91] ^= 1;
 
-		link_a.minimize_cost(link_c);
+		let mut decoder = BitDecoderState::with_code(&code, bits.len());
+		for symbols in encoded.chunks_exact(2) {
+			decoder.push(symbols);
+		}
 
-		assert_eq!(link_a.prev_state, 2);
-		assert_eq!(link_a.cost, 9);
+		assert_eq!(decoder.path_metric(), 2);
+		assert_eq!(decoder.read(1), bits);
 	}
 
 	#[test]
-	fn test_next_link() {
-		let arr = Link::next(1, 2, 0);
-
-		assert_eq!(arr[0].0, Link {
-			prev_state: 1,
-			cost: 0
-		});
+	fn test_normalize() {
+		let code = Code::new(5, &[0o23, 0o35]);
+		let bits: Vec<u8> = (0..100).map(|i| ((i * 5) % 7 % 2) as u8).collect();
+
+		let mut encoded = encode(&code, &bits);
+		encoded[20] ^= 1;
+		encoded[91] ^= 1;
+
+		let mut decoder = BitDecoderState::with_code(&code, bits.len());
+		for (i, symbols) in encoded.chunks_exact(2).enumerate() {
+			decoder.push(symbols);
+
+			// as if a long stream had already gone by, so the costs get normalised on the next push
+			if i == 50 {
+				for cost in decoder.costs.iter_mut().filter(|cost| **cost != Trellis::UNREACHED) {
+					*cost += 1 << 31;
+				}
+			}
+		}
 
-		assert_eq!(arr[1].0, Link {
-			prev_state: 1,
-			cost: 2
-		});
+		assert!(decoder.normalized >= 1 << 31);
+		assert_eq!(decoder.path_metric(), (1 << 31) + 2);
+		assert_eq!(decoder.read(1), bits);
 	}
 
 	#[test]
-	fn test_generate_link() {
-		let (link_0, _) = Link::generate(1, 2, 0, 1);
+	fn test_terminated() {
+		let code = Code::new(5, &[0o23, 0o33]);
+		let mut bits: Vec<u8> = (0..60).map(|i| ((i * 5) % 7 % 2) as u8).collect();
+		bits.extend([0; 4]);
+
+		// errors in the last few symbols make the path to another end state look cheaper
+		let mut encoded = encode(&code, &bits);
+		let len = encoded.len();
+		for i in [len - 1, len - 3, len - 4] {
+			encoded[i] ^= 1;
+		}
+
+		let decode = |terminated| {
+			let mut decoder = BitDecoderState::with_code(&code, bits.len());
+			decoder.set_terminated(terminated);
+
+			for symbols in encoded.chunks_exact(2) {
+				decoder.push(symbols);
+			}
+
+			decoder.read(1)
+		};
 
-		assert_eq!(link_0, Link {
-			prev_state: 1,
-			cost: 2
-		});
+		assert_ne!(decode(false), bits);
+		assert_eq!(decode(true), bits);
 	}
 }
@@ -1,200 +1,402 @@
-#![allow(dead_code)]
-
+use crate::code::{Code, Trellis};
 use crate::common::*;
-use crate::encode::EncoderState;
 
-use std::rc::Rc;
+use std::collections::VecDeque;
+use std::sync::Arc;
 
-#[derive(Debug)]
+/// survivor paths are stored as nodes in an arena, each pointing back to the node before it.
+///
+/// nodes that are no longer part of any survivor path are cleaned up by [`BitDecoderState::collect_garbage`]
+/// once the arena has grown enough, so memory use tracks the surviving paths instead of every branch
+/// ever considered.
+#[derive(Debug, Clone)]
 pub struct BitDecoderState {
-	end_links: [Option<RcLink>; 4],
+	arena: Vec<Node>,
+	code: Arc<Trellis>,
+	/// the cost of the survivor path ending in each state
+	costs: Vec<u32>,
+	/// the node at the end of the survivor path ending in each state
+	ends: Vec<u32>,
+	/// scratch space for the next `costs` and `ends`
+	new_costs: Vec<u32>,
+	new_ends: Vec<u32>,
+	/// scratch space for the branch metrics of the current push
+	metrics: Vec<u32>,
 	bit: u8,
 	len: usize, // should it be a u32?
+	/// the arena size at which garbage will next be collected
+	gc_threshold: usize,
+	/// look for survivor paths merging while pushing, and make the bits before the merge available early
+	early_output: bool,
+	/// read the path ending in state 0 instead of the cheapest one
+	terminated: bool,
+	/// the newest node that every survivor path goes through, and so has already been settled
+	settled_node: u32,
+	/// how many bits have been settled in total
+	settled_len: usize,
+	/// settled bits that haven't been taken yet
+	settled: VecDeque<u8>,
+	/// scratch space for walking back along the survivor paths in [`BitDecoderState::settle`]
+	merge_nodes: Vec<u32>,
 }
 
 impl BitDecoderState {
-	const EMPTY: [Option<RcLink>; 4] = [None, None, None, None];
+	/// the smallest arena that garbage will be collected from
+	const MIN_GC_THRESHOLD: usize = 1024;
 
 	/// create a new decoder for a single bit.
 	pub fn new(bit: u8) -> Self {
+		Self::with_code(&Code::default(), bit)
+	}
+
+	/// the same as [`BitDecoderState::new`], but decodes `code` instead of the default code
+	pub fn with_code(code: &Code, bit: u8) -> Self {
+		Self::from_trellis(Arc::new(Trellis::new(code)), bit)
+	}
+
+	pub(crate) fn from_trellis(code: Arc<Trellis>, bit: u8) -> Self {
+		let states = code.states();
+
 		Self {
-			end_links: Self::EMPTY,
+			arena: Vec::new(),
+			costs: code.start_costs(),
+			ends: vec![Node::NONE; states],
+			new_costs: vec![Trellis::UNREACHED; states],
+			new_ends: vec![Node::NONE; states],
+			metrics: vec![0; code.symbols()],
+			code,
 			bit,
-			len: 0
+			len: 0,
+			gc_threshold: Self::MIN_GC_THRESHOLD,
+			early_output: false,
+			terminated: false,
+			settled_node: Node::NONE,
+			settled_len: 0,
+			settled: VecDeque::new(),
+			merge_nodes: Vec::with_capacity(states),
 		}
 	}
 
-	fn is_empty(&self) -> bool {
-		self.end_links == Self::EMPTY
+	/// output bits as soon as all the survivor paths agree on them.
+	///
+	/// memory use then only depends on how far back the paths merge, instead of on the length of the stream
+	pub fn set_early_output(&mut self, early_output: bool) {
+		self.early_output = early_output;
 	}
 
-	/// push a pair of bits to be decoded
+	/// the encoder was brought back to state 0 by tail bits at the end, so read the path ending in
+	/// state 0 instead of whichever one is cheapest
+	pub fn set_terminated(&mut self, terminated: bool) {
+		self.terminated = terminated;
+	}
+
+	pub fn code(&self) -> &Code {
+		self.code.code()
+	}
+
+	/// push the symbols output by the encoder for a single bit (one per output of the code)
 	///
 	/// takes u8s instead of bools for conveince (just do a bitwise and between the mask and the byte)
-	pub fn push(&mut self, s0: u8, s1: u8) {
-		let bit_pair = combine(s0, s1);
+	pub fn push(&mut self, symbols: &[u8]) {
+		self.code.hard_metrics(symbols, &mut self.metrics);
+		self.step();
+	}
 
-		let mut new_endlinks = Self::EMPTY;
+	/// push the soft symbols received for a single bit (one per output of the code).
+	///
+	/// positive means 1 and negative means 0, with 0 meaning nothing is known (like for punctured symbols)
+	pub fn push_soft(&mut self, symbols: &[i8]) {
+		self.code.soft_metrics(symbols, &mut self.metrics);
+		self.step();
+	}
 
-		if !self.is_empty() {
-			let mut link_vec = Vec::with_capacity(8);
-			for each in &self.end_links {
-				if let Some(link) = each {
-					let link_pair = Link::next_links(link, bit_pair, self.bit);
-					link_vec.extend_from_slice(&link_pair);
-				}
+	/// move forward one step using the branch metrics in `self.metrics`
+	fn step(&mut self) {
+		self.new_costs.fill(Trellis::UNREACHED);
+
+		let costs = &self.costs;
+		let ends = &self.ends;
+		let new_costs = &mut self.new_costs;
+		let new_ends = &mut self.new_ends;
+
+		self.code.acs(&self.metrics, |state| costs[state], |state, prev, cost| {
+			new_costs[state] = cost;
+			new_ends[state] = ends[prev];
+		});
+
+		// only the branches that survived get a node
+		for state in 0..self.code.states() {
+			if self.new_costs[state] != Trellis::UNREACHED {
+				let bit = map_to((state & 1) as u8, self.bit);
+				self.new_ends[state] = self.alloc(self.new_ends[state], bit);
 			}
+		}
 
-			let mut min_costs = [u32::MAX; 4];
+		std::mem::swap(&mut self.costs, &mut self.new_costs);
+		std::mem::swap(&mut self.ends, &mut self.new_ends);
+		Trellis::normalize(&mut self.costs);
+		self.len += 1;
 
-			for each in link_vec {
-				let index = each.position();
-				if each.cost < min_costs[index] {
-					min_costs[index] = each.cost;
-					new_endlinks[index] = Some(each.to_rc_link());
-				}
-			}
-		} else {
-			for each in Link::first_links(bit_pair, self.bit) {
-				let index = each.position();
-				new_endlinks[index] = Some(each.to_rc_link());
-			}
+		if self.early_output {
+			self.settle();
 		}
 
-		self.end_links = new_endlinks;
-		self.len += 1;
+		if self.arena.len() >= self.gc_threshold {
+			self.collect_garbage();
+		}
 	}
 
-	// TODO: optimize
-	pub fn read(&self) -> Vec<u8> {
-		let mut ans = vec![0; self.len];
-
-		let mut link = self.end_links.iter()
-			.min_by_key(|link| link.as_ref().unwrap().cost)
-			.unwrap().clone().unwrap();
+	/// the nodes at the end of every survivor path
+	fn end_nodes(&self) -> impl Iterator<Item = u32> + '_ {
+		self.costs.iter()
+			.zip(&self.ends)
+			.filter(|(cost, _)| **cost != Trellis::UNREACHED)
+			.map(|(_, node)| *node)
+	}
 
-		for backwards_index in 1..=self.len {
-			let i = self.len - backwards_index;
-			ans[i] = link.bit;
-			link = link.prev_link.clone().unwrap_or_else(|| Link::DEAD_LINK.to_rc_link());
+	fn best_end(&self) -> u32 {
+		if self.terminated {
+			return self.ends[0];
 		}
 
+		let state = self.costs.iter()
+			.enumerate()
+			.min_by_key(|(_, cost)| **cost)
+			.unwrap().0;
+
+		self.ends[state]
+	}
+
+	/// returns the settled bits that haven't been taken yet, followed by the rest of the best path
+	pub fn read(&self) -> Vec<u8> {
+		let mut ans = vec![0; self.unread_len()];
+		self.read_into(&mut ans);
+
 		ans
 	}
-}
 
-type RcLink = Rc<Link>;
+	/// does the same as [`BitDecoderState::read`], but combines the bits into `arr` using a bitwise or.
+	///
+	/// `arr` must be exactly [`BitDecoderState::unread_len`] long
+	pub fn read_into(&self, arr: &mut [u8]) {
+		assert_eq!(arr.len(), self.unread_len());
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct Link {
-	pub prev_link: Option<RcLink>,
-	pub bit: u8,
-	pub state: u8, // should I remove this? it is usefull for debuging and doesn't cost more memory
-	pub cost: u32, // enough for just over 2 gigabytes/gibibytes
-}
+		let start = self.settled.len();
 
-impl Link {
-	/// reads the bits of self into the slice given, combining using a bitwise or.
-	/// it starts at the end and works towards the start
-	/// NOTE: it must be long enough
-	// pub fn read_into(&self, arr: &mut [u8]) {
-	// 	let mut next = self.link_to();
-	// 	let mut i = arr.len();
+		for (byte, bit) in arr.iter_mut().zip(&self.settled) {
+			*byte |= bit;
+		}
 
-	// 	while let Some(link) = next {
-	// 		i -= 1;
+		let mut node = self.best_end();
 
-	// 		arr[i] |= link.bit;
-	// 		next = link.prev_link;
-	// 	}
-	// }
+		for i in (start..arr.len()).rev() {
+			arr[i] |= self.arena[node as usize].bit;
+			node = self.arena[node as usize].prev;
+		}
 
-	pub fn first_links(bit_pair: u8, bit: u8) -> [Self; 2] {
-		let starting_link = Self::DEAD_LINK.to_rc_link();
+		debug_assert!(node == Node::NONE || node == self.settled_node);
+	}
 
-		let mut links = [
-			Self::new_link(&starting_link, bit_pair, bit),
-			Self::new_link(&starting_link, bit_pair, 0),
-		];
+	/// how many bits [`BitDecoderState::read`] would return
+	pub fn unread_len(&self) -> usize {
+		self.settled.len() + self.len - self.settled_len
+	}
 
-		links[0].remove_prev_link();
-		links[1].remove_prev_link();
+	/// forget everything that has been pushed, keeping the memory that was allocated for it
+	pub fn reset(&mut self) {
+		self.arena.clear();
+		Trellis::reset_costs(&mut self.costs);
+		self.ends.fill(Node::NONE);
+		self.len = 0;
+		self.gc_threshold = Self::MIN_GC_THRESHOLD;
+		self.settled_node = Node::NONE;
+		self.settled_len = 0;
+		self.settled.clear();
+	}
 
-		links
+	/// true if there is a settled bit waiting to be taken
+	pub fn has_settled(&self) -> bool {
+		!self.settled.is_empty()
 	}
 
-	pub fn next_links(link: &RcLink, bit_pair: u8, bit: u8) -> [Self; 2] {
-		[
-			Self::new_link(link, bit_pair, bit),
-			Self::new_link(link, bit_pair, 0),
-		]
+	/// take the oldest settled bit
+	pub fn pop_settled(&mut self) -> Option<u8> {
+		self.settled.pop_front()
 	}
 
-	fn new_link(link: &RcLink, bit_pair: u8, bit: u8) -> Self {
-		// TODO: see if making this use a seperate, more simple encoder (a lookup table?) would be faster
-		// create an encoder to figure stuff out
-		let mut encoder: EncoderState<u8> = link.encoder();
-		let hypothetical_bit_pair = encoder.push_return_bitpair(stretch(bit));
+	/// walk back along every survivor path until they merge. if they merge somewhere newer than the
+	/// last merge, the bits up to that point are final and get moved into `settled`.
+	///
+	/// all the end links were placed in the same push, so stepping back once on each keeps them level.
+	fn settle(&mut self) {
+		let mut nodes = std::mem::take(&mut self.merge_nodes);
+		nodes.clear();
+		nodes.extend(self.end_nodes());
+
+		while nodes.iter().any(|node| *node != nodes[0]) {
+			for node in nodes.iter_mut() {
+				*node = self.arena[*node as usize].prev;
+			}
+		}
 
-		Self {
-			prev_link: Self::link_to(link),
-			bit,
-			state: encoder.into(),
-			cost: link.cost + Self::hamming_dist(bit_pair, hypothetical_bit_pair),
+		let merge = nodes[0];
+		self.merge_nodes = nodes;
+
+		if merge == self.settled_node {
+			return;
 		}
-	}
 
-	#[inline]
-	pub fn link_to(link: &RcLink) -> Option<RcLink> {
-		Some(Rc::clone(link))
-	}
+		let start = self.settled.len();
+		let mut node = merge;
 
-	#[inline]
-	pub fn to_rc_link(self) -> RcLink {
-		Rc::new(self)
-	}
+		while node != self.settled_node {
+			self.settled.push_back(self.arena[node as usize].bit);
+			node = self.arena[node as usize].prev;
+		}
 
-	const DEAD_LINK: Self = Self {
-		prev_link: None,
-		bit: 0,
-		state: 0,
-		cost: 0,
-	};
+		self.settled_len += self.settled.len() - start;
+		self.settled.make_contiguous()[start..].reverse();
 
-	#[inline]
-	fn remove_prev_link(&mut self) {
-		self.prev_link = None;
+		// cut the path off at the merge so everything before it can be collected
+		self.arena[merge as usize].prev = Node::NONE;
+		self.settled_node = merge;
 	}
 
-	#[inline]
-	fn encoder(&self) -> EncoderState<u8> {
-		self.state.into()
-	}
+	fn alloc(&mut self, prev: u32, bit: u8) -> u32 {
+		let index = self.arena.len() as u32;
+		assert!(index != Node::NONE, "survivor path arena is full");
 
-	/// get the index where this link belongs in the `end_links` array
-	#[inline]
-	pub fn position(&self) -> usize {
-		self.state as usize
+		self.arena.push(Node { prev, bit });
+		index
 	}
 
-	#[inline]
-	fn hamming_dist(a: u8, b: u8) -> u32 {
-		(a ^ b).count_ones() as u32
+	/// remove every node that can't be reached from the end of a survivor path, compacting the arena.
+	///
+	/// nodes only ever point to nodes before them, so the order is kept and a single pass is enough
+	/// to remap the indices.
+	fn collect_garbage(&mut self) {
+		let mut reachable = vec![false; self.arena.len()];
+
+		for mut node in self.end_nodes() {
+
+			while node != Node::NONE && !reachable[node as usize] {
+				reachable[node as usize] = true;
+				node = self.arena[node as usize].prev;
+			}
+		}
+
+		let mut new_index = vec![Node::NONE; self.arena.len()];
+		let mut kept = 0;
+
+		for i in 0..self.arena.len() {
+			if reachable[i] {
+				let mut node = self.arena[i].clone();
+				if node.prev != Node::NONE {
+					node.prev = new_index[node.prev as usize];
+				}
+
+				new_index[i] = kept as u32;
+				self.arena[kept] = node;
+				kept += 1;
+			}
+		}
+
+		self.arena.truncate(kept);
+
+		for (node, cost) in self.ends.iter_mut().zip(&self.costs) {
+			if *cost != Trellis::UNREACHED {
+				*node = new_index[*node as usize];
+			}
+		}
+
+		if self.settled_node != Node::NONE {
+			self.settled_node = new_index[self.settled_node as usize];
+		}
+
+		self.gc_threshold = Self::MIN_GC_THRESHOLD.max(kept * 2);
 	}
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+	/// index of the previous node in the arena
+	pub prev: u32,
+	pub bit: u8,
+}
+
+impl Node {
+	/// marks the start of a path
+	const NONE: u32 = u32::MAX;
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::encode::EncoderState;
+
+	#[test]
+	fn test_garbage_collection() {
+		let bits: Vec<u8> = (0..5000).map(|i| ((i * 13) % 7 % 2) as u8).collect();
+
+		let mut encoder: EncoderState<u8> = EncoderState::default();
+		let mut decoder = BitDecoderState::new(1);
+
+		for byte in &bits {
+			let (s0, s1) = encoder.push(*byte);
+			decoder.push(&[s0 & 1, s1 & 1]);
+		}
+
+		// without collection there would be 4 nodes per bit
+		assert!(decoder.arena.len() < bits.len() * 3);
+		assert_eq!(decoder.read(), bits);
+	}
+
+	#[test]
+	fn test_early_output() {
+		let bits: Vec<u8> = (0..5000).map(|i| ((i * 13) % 7 % 2) as u8).collect();
+
+		let mut encoder: EncoderState<u8> = EncoderState::default();
+		let mut decoder = BitDecoderState::new(1);
+		decoder.set_early_output(true);
+		let mut output = Vec::new();
+
+		for byte in &bits {
+			let (s0, s1) = encoder.push(*byte);
+			decoder.push(&[s0 & 1, s1 & 1]);
+
+			while let Some(bit) = decoder.pop_settled() {
+				output.push(bit);
+			}
+		}
+
+		// the paths of a K=3 code merge quickly, so nearly everything is settled early
+		assert!(output.len() > bits.len() - 32);
+		assert!(decoder.arena.len() <= BitDecoderState::MIN_GC_THRESHOLD);
+
+		output.extend(decoder.read());
+		assert_eq!(output, bits);
+	}
+
+	#[test]
+	fn test_early_output_with_errors() {
+		let bits: Vec<u8> = (0..300).map(|i| ((i * 3) % 11 % 2) as u8).collect();
+
+		let mut encoder: EncoderState<u8> = EncoderState::default();
+		let mut decoder = BitDecoderState::new(1);
+		decoder.set_early_output(true);
+
+		for (i, byte) in bits.iter().enumerate() {
+			let (s0, s1) = encoder.push(*byte);
+			let s1 = if i % 50 == 25 { s1 ^ 1 } else { s1 };
+			decoder.push(&[s0 & 1, s1 & 1]);
+		}
+
+		assert_eq!(decoder.read(), bits);
+	}
 
 	#[test]
-	fn test_hamming_distance() {
-		assert_eq!(Link::hamming_dist(255, 0), 8);
-		assert_eq!(Link::hamming_dist(1, 0), 1);
-		assert_eq!(Link::hamming_dist(2, 0), 1);
-		assert_eq!(Link::hamming_dist(2, 1), 2);
-		assert_eq!(Link::hamming_dist(0, 0), 0);
-		assert_eq!(Link::hamming_dist(255, 255), 0);
-		assert_eq!(Link::hamming_dist(0b00010101, 0b00000100), 2);
+	fn test_send_sync() {
+		fn is_send_sync<T: Send + Sync>() {}
+		is_send_sync::<BitDecoderState>();
 	}
 }
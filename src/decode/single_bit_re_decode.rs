@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use crate::code::{Code, Trellis};
+use crate::common::*;
+
+/// how many decoded bits each state's register holds before the oldest one is output
+const REGISTER_LEN: u32 = u64::BITS;
+
+/// a register exchange decoder for a single bit.
+///
+/// instead of keeping a trellis around and walking it backwards, each state carries the bits that
+/// were decoded on the way to it. once the registers are full the oldest bit of the best state is
+/// output, so no traceback is ever needed.
+///
+/// the registers are 64 bits long, which is plenty for the paths of small codes to have merged by
+/// the time a bit is output, but will start costing accuracy past K=9 or so
+#[derive(Debug, Clone)]
+pub struct BitDecoderState {
+	code: Arc<Trellis>,
+	costs: Vec<u32>,
+	registers: Vec<u64>,
+	/// scratch space for the next `costs` and `registers`
+	new_costs: Vec<u32>,
+	new_registers: Vec<u64>,
+	/// scratch space for the branch metrics of the current push
+	metrics: Vec<u32>,
+	/// how many bits of the registers are in use
+	depth: u32,
+	output: Vec<u8>,
+	bit: u8,
+	/// read the register of state 0 at the end instead of the cheapest one
+	terminated: bool,
+}
+
+impl BitDecoderState {
+	/// create a new decoder for a single bit.
+	pub fn new(bit: u8) -> Self {
+		Self::with_code(&Code::default(), bit)
+	}
+
+	/// the same as [`BitDecoderState::new`], but decodes `code` instead of the default code
+	pub fn with_code(code: &Code, bit: u8) -> Self {
+		Self::from_trellis(Arc::new(Trellis::new(code)), bit)
+	}
+
+	pub(crate) fn from_trellis(code: Arc<Trellis>, bit: u8) -> Self {
+		let states = code.states();
+
+		Self {
+			costs: code.start_costs(),
+			registers: vec![0; states],
+			new_costs: vec![Trellis::UNREACHED; states],
+			new_registers: vec![0; states],
+			metrics: vec![0; code.symbols()],
+			code,
+			depth: 0,
+			output: Vec::new(),
+			bit,
+			terminated: false,
+		}
+	}
+
+	/// the encoder was brought back to state 0 by tail bits at the end, so read the register of state 0
+	/// at the end instead of whichever one is cheapest. bits output while pushing are unaffected
+	pub fn set_terminated(&mut self, terminated: bool) {
+		self.terminated = terminated;
+	}
+
+	pub fn code(&self) -> &Code {
+		self.code.code()
+	}
+
+	/// push the symbols output by the encoder for a single bit (one per output of the code)
+	///
+	/// takes u8s instead of bools for conveince (just do a bitwise and between the mask and the byte)
+	pub fn push(&mut self, symbols: &[u8]) {
+		self.code.hard_metrics(symbols, &mut self.metrics);
+		self.step();
+	}
+
+	/// push the soft symbols received for a single bit (one per output of the code).
+	///
+	/// positive means 1 and negative means 0, with 0 meaning nothing is known (like for punctured symbols)
+	pub fn push_soft(&mut self, symbols: &[i8]) {
+		self.code.soft_metrics(symbols, &mut self.metrics);
+		self.step();
+	}
+
+	/// move forward one step using the branch metrics in `self.metrics`
+	fn step(&mut self) {
+		if self.depth == REGISTER_LEN {
+			let oldest = self.registers[self.best_state()] >> (REGISTER_LEN - 1);
+			self.output.push(map_to(oldest as u8, self.bit));
+			self.depth -= 1;
+		}
+
+		self.new_costs.fill(Trellis::UNREACHED);
+
+		let costs = &self.costs;
+		let registers = &self.registers;
+		let new_costs = &mut self.new_costs;
+		let new_registers = &mut self.new_registers;
+
+		self.code.acs(&self.metrics, |state| costs[state], |state, prev, cost| {
+			new_costs[state] = cost;
+			new_registers[state] = (registers[prev] << 1) | (state & 1) as u64;
+		});
+
+		std::mem::swap(&mut self.costs, &mut self.new_costs);
+		std::mem::swap(&mut self.registers, &mut self.new_registers);
+		Trellis::normalize(&mut self.costs);
+		self.depth += 1;
+	}
+
+	/// ouputs a vector of u8s where only the correct bits are set to 1
+	pub fn read(&self) -> Vec<u8> {
+		let mut ans = vec![0; self.len()];
+		self.read_into(&mut ans);
+
+		ans
+	}
+
+	/// does the same as [`BitDecoderState::read`], but combines the bits into `arr` using a bitwise or.
+	///
+	/// `arr` must be exactly as long as the number of bits pushed
+	pub fn read_into(&self, arr: &mut [u8]) {
+		assert_eq!(arr.len(), self.len());
+
+		let (output, registers) = arr.split_at_mut(self.output.len());
+
+		for (byte, bit) in output.iter_mut().zip(&self.output) {
+			*byte |= bit;
+		}
+
+		let end = if self.terminated { 0 } else { self.best_state() };
+		let register = self.registers[end];
+
+		for (i, byte) in registers.iter_mut().rev().enumerate() {
+			*byte |= map_to(((register >> i) & 1) as u8, self.bit);
+		}
+	}
+
+	/// the number of bits pushed
+	pub fn len(&self) -> usize {
+		self.output.len() + self.depth as usize
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// forget everything that has been pushed, keeping the memory that was allocated for it
+	pub fn reset(&mut self) {
+		Trellis::reset_costs(&mut self.costs);
+		self.registers.fill(0);
+		self.depth = 0;
+		self.output.clear();
+	}
+
+	fn best_state(&self) -> usize {
+		self.costs.iter()
+			.enumerate()
+			.min_by_key(|(_, cost)| **cost)
+			.unwrap().0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::encode::EncoderState;
+
+	fn decode(bytes: &[u8]) -> Vec<u8> {
+		let mut encoder: EncoderState<u8> = EncoderState::default();
+		let mut decoder = BitDecoderState::new(1);
+
+		for byte in bytes {
+			let (s0, s1) = encoder.push(*byte);
+			decoder.push(&[s0 & 1, s1 & 1]);
+		}
+
+		decoder.read()
+	}
+
+	#[test]
+	fn test_short_stream() {
+		let bits = vec![1, 0, 1, 1, 0, 0, 1];
+		assert_eq!(decode(&bits), bits);
+	}
+
+	#[test]
+	fn test_stream_longer_than_register() {
+		let bits: Vec<u8> = (0..300).map(|i| ((i * 7) % 5 % 2) as u8).collect();
+		assert_eq!(decode(&bits), bits);
+	}
+
+	#[test]
+	fn test_corrects_single_error() {
+		let bits: Vec<u8> = (0..100).map(|i| ((i * 3) % 7 % 2) as u8).collect();
+
+		let mut encoder: EncoderState<u8> = EncoderState::default();
+		let mut decoder = BitDecoderState::new(1);
+
+		for (i, byte) in bits.iter().enumerate() {
+			let (s0, s1) = encoder.push(*byte);
+			let s0 = if i == 40 { s0 ^ 1 } else { s0 };
+			decoder.push(&[s0 & 1, s1 & 1]);
+		}
+
+		assert_eq!(decoder.read(), bits);
+	}
+}
@@ -0,0 +1,85 @@
+use super::BitDecoderState;
+use crate::code::Code;
+
+/// decode a tail biting code, where the encoder starts in the state its last K - 1 inputs leave it
+/// in instead of state 0 (see [`EncoderState::preload`](crate::prelude::EncoderState::preload)).
+///
+/// `symbols` are the soft symbols output for each bit one after another (see [`BitDecoderState::push_soft`]),
+/// and the decoded bits (each 0 or 1) are returned.
+///
+/// since the start and end states are the same but unknown, the symbols are treated as a loop: the
+/// decoder is started in any state some way before the start, and traced back from some way past the
+/// end. by then the paths have settled, so only the bits in the middle are kept
+pub fn decode_tail_biting(code: &Code, symbols: &[i8]) -> Vec<u8> {
+	let outputs = code.outputs();
+	assert_eq!(symbols.len() % outputs, 0, "symbols must be a whole number of bits");
+
+	let len = symbols.len() / outputs;
+	if len == 0 {
+		return Vec::new();
+	}
+
+	// long enough for the survivor paths to merge
+	let wrap = code.constraint_len() as usize * 6;
+
+	let mut decoder = BitDecoderState::with_code(code, len + wrap * 2);
+	decoder.set_any_start(true);
+
+	for i in 0..len + wrap * 2 {
+		let bit = (i + len * wrap - wrap) % len;
+		decoder.push_soft(&symbols[bit * outputs..(bit + 1) * outputs]);
+	}
+
+	let mut bits = decoder.read(1);
+	bits.truncate(wrap + len);
+	bits.drain(..wrap);
+
+	bits
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::encode::EncoderState;
+	use crate::presets::soft_symbols;
+
+	fn encode_tail_biting(code: &Code, bits: &[u8]) -> Vec<u8> {
+		let mut encoder = EncoderState::<u8>::new(code.clone());
+		let tail = bits.len().saturating_sub(code.constraint_len() as usize - 1);
+		encoder.preload(&bits[tail..]);
+
+		encoder.push_slice(bits)
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let code = Code::new(7, &[0o133, 0o171, 0o165]);
+		let bits: Vec<u8> = (0..120).map(|i| ((i * 7) % 11 % 2) as u8).collect();
+
+		let symbols = soft_symbols(&encode_tail_biting(&code, &bits));
+		assert_eq!(decode_tail_biting(&code, &symbols), bits);
+	}
+
+	#[test]
+	fn test_shorter_than_wrap() {
+		let code = Code::default();
+		let bits = vec![1, 0, 1, 1, 0, 0, 0, 1, 1, 1];
+
+		let symbols = soft_symbols(&encode_tail_biting(&code, &bits));
+		assert_eq!(decode_tail_biting(&code, &symbols), bits);
+	}
+
+	#[test]
+	fn test_corrects_errors_near_the_ends() {
+		let code = Code::new(7, &[0o133, 0o171, 0o165]);
+		let bits: Vec<u8> = (0..80).map(|i| ((i * 5) % 13 % 2) as u8).collect();
+
+		let mut symbols = soft_symbols(&encode_tail_biting(&code, &bits));
+		let last = symbols.len() - 1;
+		symbols[1] = -symbols[1];
+		symbols[last] = -symbols[last];
+		symbols[100] = -symbols[100];
+
+		assert_eq!(decode_tail_biting(&code, &symbols), bits);
+	}
+}
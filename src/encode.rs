@@ -1,19 +1,76 @@
-use std::ops::BitXor;
+use std::ops::{BitXor, Not};
 
+use crate::code::Code;
 use crate::common::*;
 
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// the size of the ring buffer holding an encoder's memory, enough for the longest codes
+const MEMORY_LEN: usize = Code::MAX_CONSTRAINT_LEN as usize;
+
+/// the inputs one output XORs together, worked out from its generator when the encoder is created
+#[derive(Debug, Clone)]
+struct OutputTaps {
+	/// whether the current input is tapped
+	current: bool,
+	/// how many inputs ago each of the other tapped inputs was, minus 1 (so an index into the memory)
+	delays: Vec<usize>,
+	inverted: bool,
+}
+
+#[derive(Debug, Clone)]
 /// represents the internal state of multiple encoders. (each bit is its own encoder)
-/// 
+///
+/// using `bool` instead gives a single encoder working on one bit at a time.
+///
 /// for more detail on how this works see [this video](https://youtu.be/kRIfpmiMCpU)
-pub struct EncoderState<T: BitXor + Copy>(T, T);
+pub struct EncoderState<T: BitXor + Copy> {
+	code: Code,
+	taps: Vec<OutputTaps>,
+	/// the last K - 1 chunks input, as a ring buffer with the most recent at `head`
+	memory: [T; MEMORY_LEN],
+	head: usize,
+}
+
+impl<T> EncoderState<T>
+where
+	T: BitXor<Output = T> + Not<Output = T> + Copy + Default,
+{
+	/// create an encoder for `code`, starting in state 0
+	pub fn new(code: Code) -> Self {
+		let taps = (0..code.outputs())
+			.map(|j| {
+				let taps = code.taps(code.generators()[j]);
+
+				OutputTaps {
+					current: taps & 1 != 0,
+					delays: (0..code.constraint_len() as usize - 1)
+						.filter(|d| taps & (2 << d) != 0)
+						.collect(),
+					inverted: code.is_inverted(j),
+				}
+			})
+			.collect();
+
+		Self {
+			code,
+			taps,
+			memory: [T::default(); MEMORY_LEN],
+			head: 0,
+		}
+	}
+
+	pub fn code(&self) -> &Code {
+		&self.code
+	}
 
-impl<T: BitXor<Output = T> + Copy> EncoderState<T> {
 	/// input a chunk to the encoder, updating state and returning the 2 chunks that should be transmitted
+	///
+	/// only works for rate 1/2 codes, use [`EncoderState::push_to`] for anything else
 	pub fn push(&mut self, chunk: T) -> (T, T) {
+		assert_eq!(self.code.outputs(), 2, "push only works for rate 1/2 codes");
+
 		let ans = (
-			self.1 ^ chunk,
-			self.0 ^ self.1 ^ chunk
+			self.output(chunk, &self.taps[0]),
+			self.output(chunk, &self.taps[1]),
 		);
 
 		self.update(chunk);
@@ -21,53 +78,133 @@ impl<T: BitXor<Output = T> + Copy> EncoderState<T> {
 		ans
 	}
 
+	/// input a chunk to the encoder, updating state and appending the chunks that should be
+	/// transmitted to `out` (one per output of the code)
+	pub fn push_to(&mut self, chunk: T, out: &mut Vec<T>) {
+		for taps in &self.taps {
+			out.push(self.output(chunk, taps));
+		}
+
+		self.update(chunk);
+	}
+
+	/// input chunks without outputting anything, just to get the encoder into the state they lead to
+	///
+	/// a tail biting encoder starts in the state its last K - 1 inputs will leave it in, so those can be preloaded
+	pub fn preload(&mut self, arr: &[T]) {
+		for each in arr {
+			self.update(*each);
+		}
+	}
+
+	pub fn push_slice(&mut self, arr: &[T]) -> Vec<T> {
+		let mut ans = Vec::with_capacity(arr.len() * self.code.outputs());
+
+		for each in arr {
+			self.push_to(*each, &mut ans);
+		}
+
+		ans
+	}
+
+	/// the chunk input `d + 1` chunks ago
+	#[inline]
+	fn delayed(&self, d: usize) -> T {
+		self.memory[(self.head + d) % MEMORY_LEN]
+	}
+
+	/// what the output with `taps` would be if `chunk` were input
+	#[inline]
+	fn output(&self, chunk: T, taps: &OutputTaps) -> T {
+		let ans = if taps.current { chunk } else { T::default() };
+		let ans = taps.delays.iter().fold(ans, |ans, d| ans ^ self.delayed(*d));
+
+		if taps.inverted {
+			!ans
+		} else {
+			ans
+		}
+	}
+
 	#[inline]
 	/// update the state.
 	fn update(&mut self, chunk: T) {
-		self.1 = self.0;
-		self.0 = chunk;
+		self.head = (self.head + MEMORY_LEN - 1) % MEMORY_LEN;
+		self.memory[self.head] = chunk;
 	}
 }
 
-impl From<u8> for EncoderState<u8> {
-	fn from(value: u8) -> Self {
-		match value {
-			0 => Self(0x00, 0x00),
-			1 => Self(0xFF, 0x00),
-			2 => Self(0x00, 0xFF),
-			3 => Self(0xFF, 0xFF),
-			_ => unreachable!()
-		}
+impl<T> PartialEq for EncoderState<T>
+where
+	T: BitXor<Output = T> + Not<Output = T> + Copy + Default + PartialEq,
+{
+	/// encoders are equal if they encode the same code and are in the same state
+	fn eq(&self, other: &Self) -> bool {
+		self.code == other.code
+			&& (0..self.code.constraint_len() as usize - 1).all(|d| self.delayed(d) == other.delayed(d))
 	}
 }
 
-impl From<EncoderState<u8>> for u8 {
-	fn from(value: EncoderState<u8>) -> Self {
-		combine(value.0, value.1)
+impl<T> Eq for EncoderState<T>
+where
+	T: BitXor<Output = T> + Not<Output = T> + Copy + Default + Eq,
+{}
+
+impl<T> Default for EncoderState<T>
+where
+	T: BitXor<Output = T> + Not<Output = T> + Copy + Default,
+{
+	/// an encoder for the default K=3 code
+	fn default() -> Self {
+		Self::new(Code::default())
 	}
 }
 
 impl EncoderState<u8> {
-	/// does the same thing as input, but it combines the 2 bytes into a bit pair
-	/// 
+	/// create an encoder for `code` in `state`, with every bit being the same encoder
+	pub fn with_state(code: Code, state: u32) -> Self {
+		let mut encoder = Self::new(code);
+
+		// oldest first, so each ends up `d` inputs ago
+		for d in (0..encoder.code.constraint_len() as usize - 1).rev() {
+			encoder.update(stretch((state >> d) as u8 & 1));
+		}
+
+		encoder
+	}
+
+	/// the state of the encoder for the lowest bit
+	pub fn state(&self) -> u32 {
+		(0..self.code.constraint_len() as usize - 1)
+			.fold(0, |acc, d| acc | (squish(self.delayed(d) & BIT_MASK[0]) as u32) << d)
+	}
+
+	/// does the same thing as input, but it combines the output bytes into bits (output `j` being bit `j`)
+	///
 	/// NOTE: this won't work in a usefull manner if you are using the EncoderState to encode multiple bits side by side
 	/// its only purpose really is for testing
 	pub fn push_return_bitpair(&mut self, byte: u8) -> u8 { // todo kill???
-		let (s0, s1) = self.push(byte);
-		combine(s0, s1)
-	}
+		let mut out = Vec::with_capacity(self.code.outputs());
+		self.push_to(byte, &mut out);
 
-	pub fn push_slice(&mut self, arr: &[u8]) -> Vec<u8> {
-		let mut ans = Vec::with_capacity(arr.len() * 2);
+		out.iter()
+			.enumerate()
+			.fold(0, |acc, (j, each)| acc | map_to(*each, BIT_MASK[j]))
+	}
+}
 
-		for each in arr {
-			let pair = self.push(*each);
-			
-			ans.push(pair.0);
-			ans.push(pair.1);
-		}
+impl From<u8> for EncoderState<u8> {
+	/// an encoder for the default K=3 code in the given state
+	fn from(value: u8) -> Self {
+		assert!(value < 4);
+		Self::with_state(Code::default(), value as u32)
+	}
+}
 
-		ans
+impl From<EncoderState<u8>> for u8 {
+	fn from(value: EncoderState<u8>) -> Self {
+		assert!(value.code.constraint_len() <= 9);
+		value.state() as u8
 	}
 }
 
@@ -80,6 +217,13 @@ mod tests {
 		assert_eq!(state, &x);
 	}
 
+	/// an encoder for the default code with `newest` and `oldest` in its memory
+	fn raw(newest: u8, oldest: u8) -> EncoderState<u8> {
+		let mut encoder = EncoderState::default();
+		encoder.preload(&[oldest, newest]);
+		encoder
+	}
+
 	#[test]
 	fn test_state_updating() {
 		let mut state = EncoderState::<u8>::default();
@@ -139,10 +283,10 @@ mod tests {
 		];
 
 		let arr_b: [EncoderState<u8>; 4] = [
-			EncoderState(0, 0),
-			EncoderState(0xFF, 0),
-			EncoderState(0, 0xFF),
-			EncoderState(0xFF, 0xFF)
+			raw(0, 0),
+			raw(0xFF, 0),
+			raw(0, 0xFF),
+			raw(0xFF, 0xFF)
 		];
 
 		assert_eq!(arr_a, arr_b);
@@ -151,14 +295,51 @@ mod tests {
 	#[test]
 	fn test_to_u8() {
 		let arr_a: [u8; 4] = [
-			EncoderState(0, 0).into(),
-			EncoderState(0xFF, 0).into(),
-			EncoderState(0, 0xFF).into(),
-			EncoderState(0xFF, 0xFF).into(),
+			raw(0, 0).into(),
+			raw(0xFF, 0).into(),
+			raw(0, 0xFF).into(),
+			raw(0xFF, 0xFF).into(),
 		];
-			
+
 		let arr_b: [u8; 4] = [0, 1, 2, 3];
 
 		assert_eq!(arr_a, arr_b);
 	}
+
+	#[test]
+	fn test_matches_code_output() {
+		let code = Code::new(5, &[0o23, 0o35, 0o37]).with_inverted(2);
+
+		for state in 0..code.states() as u32 {
+			for bit in 0..2 {
+				let mut encoder = EncoderState::with_state(code.clone(), state);
+
+				assert_eq!(encoder.push_return_bitpair(stretch(bit)), code.output(state, bit));
+				assert_eq!(encoder.state(), code.next_state(state, bit));
+			}
+		}
+	}
+
+	#[test]
+	fn test_preload() {
+		let mut encoder = EncoderState::<u8>::default();
+		encoder.preload(&[0xFF, 0x00, 0xFF]);
+
+		state_eq(&encoder, 1);
+	}
+
+	#[test]
+	fn test_bool_encoder() {
+		let bits = [true, false, true, true, false, false, true, false];
+
+		let mut serial = EncoderState::<bool>::default();
+		let mut lanes = EncoderState::<u8>::default();
+
+		for bit in bits {
+			let (a, b) = serial.push(bit);
+			let (x, y) = lanes.push(stretch(bit as u8));
+
+			assert_eq!((stretch(a as u8), stretch(b as u8)), (x, y));
+		}
+	}
 }
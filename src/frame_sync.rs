@@ -0,0 +1,457 @@
+//! finding frames in a continuous stream of symbols by the sync marker attached to the start of each.
+//!
+//! [`FrameSync`] takes soft symbols as they arrive and hands back each frame once all of it has arrived,
+//! lined up so it starts at the symbol after the marker. it works the same whichever domain the marker is
+//! in:
+//!
+//! - a marker attached after encoding (or to data that isn't encoded) is searched for as it is, and the
+//!   frames are the encoded symbols. if they are the soft symbols for the bytes output by
+//!   [`EncoderState<u8>`](crate::prelude::EncoderState), they can be pushed straight into a decoder
+//!   with [`Decoder::push_soft_slice`](crate::prelude::Decoder::push_soft_slice)
+//! - a marker that is encoded along with the data (as CCSDS does) can be searched for in the encoded
+//!   symbols using [`Marker::encoded`], or in the decoded bits (as soft symbols) using the marker as it is
+//!
+//! like most receivers, it goes through a few states: it searches for the marker anywhere, then checks that
+//! it is there again a frame later a few times before it is locked. once locked, a few missed markers are
+//! put up with (the flywheel) before searching again, as a marker with too many errors is much more
+//! likely than the frames having moved.
+
+use crate::code::Code;
+use crate::common::unpack_bits;
+use crate::presets::encode_bits;
+
+/// a sync marker, as the symbols expected for it. some can be left as don't care
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+	pattern: Vec<Option<bool>>,
+}
+
+impl Marker {
+	/// the marker made of `bytes`, sent most significant bit first
+	pub fn new(bytes: &[u8]) -> Self {
+		Self::from_bits(&unpack_bits(bytes))
+	}
+
+	/// the marker made of `bits` (each 0 or not 0), in the order they are sent
+	pub fn from_bits(bits: &[u8]) -> Self {
+		assert!(!bits.is_empty());
+
+		Self {
+			pattern: bits.iter().map(|bit| Some(*bit != 0)).collect(),
+		}
+	}
+
+	/// the CCSDS attached sync marker, 0x1ACFFC1D
+	pub fn ccsds() -> Self {
+		Self::new(&[0x1A, 0xCF, 0xFC, 0x1D])
+	}
+
+	/// the symbols `code` outputs for this marker, for finding a marker that was encoded along with the
+	/// data. the symbols that depend on the bits before the marker are don't care
+	pub fn encoded(&self, code: &Code) -> Self {
+		let bits: Vec<u8> = self.pattern.iter()
+			.map(|bit| bit.unwrap_or(false) as u8)
+			.collect();
+
+		let symbols = encode_bits(code, &bits);
+		let outputs = code.outputs();
+
+		let pattern = symbols.iter()
+			.enumerate()
+			.map(|(i, symbol)| {
+				let (t, j) = (i / outputs, i % outputs);
+				let taps = code.taps(code.generators()[j]);
+
+				// bit d of the taps is the bit input d steps ago, which has to be part of the marker,
+				// and so does every bit the output depends on
+				let known = taps >> (t + 1) == 0
+					&& (0..=t).all(|d| taps >> d & 1 == 0 || self.pattern[t - d].is_some());
+
+				known.then_some(*symbol != 0)
+			})
+			.collect();
+
+		Self { pattern }
+	}
+
+	/// the number of symbols in the marker
+	pub fn len(&self) -> usize {
+		self.pattern.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pattern.is_empty()
+	}
+
+	/// the number of symbols in `symbols` (at least as long as the marker) that don't match it.
+	/// erasures count as not matching
+	pub fn errors(&self, symbols: &[i8]) -> usize {
+		self.pattern.iter()
+			.zip(symbols)
+			.filter(|(expected, symbol)| match expected {
+				Some(bit) => **symbol == 0 || (**symbol > 0) != *bit,
+				None => false,
+			})
+			.count()
+	}
+}
+
+/// how sure [`FrameSync`] is of where the frames are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+	/// looking for the marker anywhere
+	Searching,
+	/// found the marker this many times in a row, but not enough to be locked
+	Verifying(usize),
+	/// found the marker enough times in a row
+	Locked,
+	/// locked, but missed the marker this many times in a row
+	Flywheel(usize),
+}
+
+/// a frame found by [`FrameSync`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+	/// the symbols after the marker
+	pub symbols: Vec<i8>,
+	/// how many symbols before the marker there were in the stream
+	pub offset: u64,
+	/// how many of the marker's symbols were wrong
+	pub marker_errors: usize,
+	/// the state after the marker was checked
+	pub state: SyncState,
+}
+
+/// finds frames of a fixed length, each starting with a [`Marker`], in a stream of soft symbols
+#[derive(Debug, Clone)]
+pub struct FrameSync {
+	marker: Marker,
+	frame_len: usize,
+	search_errors: usize,
+	lock_errors: usize,
+	verify: usize,
+	flywheel: usize,
+	state: SyncState,
+	/// the symbols that haven't been handed back yet
+	buffer: Vec<i8>,
+	/// where in `buffer` the next marker is expected, or to search from
+	pos: usize,
+	/// how many symbols have been removed from the start of `buffer`
+	dropped: u64,
+}
+
+impl FrameSync {
+	/// find frames of `frame_len` symbols (not counting the marker), each starting with `marker`
+	pub fn new(marker: Marker, frame_len: usize) -> Self {
+		Self {
+			marker,
+			frame_len,
+			search_errors: 2,
+			lock_errors: 4,
+			verify: 2,
+			flywheel: 3,
+			state: SyncState::Searching,
+			buffer: Vec::new(),
+			pos: 0,
+			dropped: 0,
+		}
+	}
+
+	/// how many wrong symbols a marker can have to be found while searching (the default is 2).
+	/// more finds markers with more errors, but also finds them where there are none
+	pub fn with_search_errors(mut self, errors: usize) -> Self {
+		self.search_errors = errors;
+		self
+	}
+
+	/// how many wrong symbols a marker can have where one is expected (the default is 4)
+	pub fn with_lock_errors(mut self, errors: usize) -> Self {
+		self.lock_errors = errors;
+		self
+	}
+
+	/// how many times in a row the marker has to be found to be locked, counting the first (the default is 2)
+	pub fn with_verify(mut self, verify: usize) -> Self {
+		assert!(verify > 0);
+
+		self.verify = verify;
+		self
+	}
+
+	/// how many markers in a row can be missed while locked before searching again (the default is 3)
+	pub fn with_flywheel(mut self, flywheel: usize) -> Self {
+		self.flywheel = flywheel;
+		self
+	}
+
+	pub fn state(&self) -> SyncState {
+		self.state
+	}
+
+	/// the symbols from the start of one marker to the start of the next
+	pub fn period(&self) -> usize {
+		self.marker.len() + self.frame_len
+	}
+
+	/// push the next symbols of the stream, returning the frames that have been completed
+	pub fn push(&mut self, symbols: &[i8]) -> Vec<Frame> {
+		self.buffer.extend_from_slice(symbols);
+
+		let marker_len = self.marker.len();
+		let period = self.period();
+		let mut frames = Vec::new();
+
+		loop {
+			if self.state == SyncState::Searching {
+				if self.pos + marker_len > self.buffer.len() {
+					break;
+				}
+
+				let errors = self.marker.errors(&self.buffer[self.pos..]);
+
+				if errors > self.search_errors {
+					self.pos += 1;
+					continue;
+				}
+
+				if self.pos + period > self.buffer.len() {
+					break;
+				}
+
+				self.state = if self.verify == 1 { SyncState::Locked } else { SyncState::Verifying(1) };
+				frames.push(self.frame(errors));
+				continue;
+			}
+
+			if self.pos + period > self.buffer.len() {
+				break;
+			}
+
+			let errors = self.marker.errors(&self.buffer[self.pos..]);
+
+			self.state = match self.state {
+				SyncState::Verifying(n) if errors <= self.search_errors => {
+					if n + 1 >= self.verify { SyncState::Locked } else { SyncState::Verifying(n + 1) }
+				}
+				SyncState::Locked | SyncState::Flywheel(_) if errors <= self.lock_errors => SyncState::Locked,
+				SyncState::Locked if self.flywheel > 0 => SyncState::Flywheel(1),
+				SyncState::Flywheel(n) if n < self.flywheel => SyncState::Flywheel(n + 1),
+				_ => SyncState::Searching,
+			};
+
+			if self.state == SyncState::Searching {
+				// the last marker may have been in the wrong place, so search again from just after it
+				self.pos = self.pos + 1 - period;
+				continue;
+			}
+
+			frames.push(self.frame(errors));
+		}
+
+		// keep the last frame, in case searching starts again from within it
+		let done = self.pos.saturating_sub(period);
+		self.buffer.drain(..done);
+		self.pos -= done;
+		self.dropped += done as u64;
+
+		frames
+	}
+
+	/// search for the marker again, forgetting everything pushed so far
+	pub fn reset(&mut self) {
+		self.state = SyncState::Searching;
+		self.buffer.clear();
+		self.pos = 0;
+		self.dropped = 0;
+	}
+
+	/// the frame with its marker at `pos`, moving past it
+	fn frame(&mut self, marker_errors: usize) -> Frame {
+		let start = self.pos + self.marker.len();
+
+		let frame = Frame {
+			symbols: self.buffer[start..start + self.frame_len].to_vec(),
+			offset: self.dropped + self.pos as u64,
+			marker_errors,
+			state: self.state,
+		};
+
+		self.pos += self.period();
+		frame
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::code::to_soft;
+
+	fn random_bits(len: usize, seed: u32) -> Vec<u8> {
+		let mut x = seed.max(1);
+
+		(0..len)
+			.map(|_| {
+				x ^= x << 13;
+				x ^= x >> 17;
+				x ^= x << 5;
+				(x & 1) as u8
+			})
+			.collect()
+	}
+
+	/// `junk` random symbols, then `frames` frames of `frame_len` random bits, each after the CCSDS marker
+	fn stream(junk: usize, frames: usize, frame_len: usize) -> (Vec<u8>, Vec<Vec<u8>>) {
+		let marker = unpack_bits(&[0x1A, 0xCF, 0xFC, 0x1D]);
+
+		let mut bits = random_bits(junk, 99);
+		let mut data = Vec::new();
+
+		for i in 0..frames {
+			let frame = random_bits(frame_len, i as u32 + 1);
+			bits.extend(&marker);
+			bits.extend(&frame);
+			data.push(frame);
+		}
+
+		(bits, data)
+	}
+
+	fn soft(bits: &[u8]) -> Vec<i8> {
+		bits.iter().map(|bit| to_soft(*bit)).collect()
+	}
+
+	#[test]
+	fn test_finds_frames() {
+		let (mut bits, data) = stream(37, 5, 100);
+
+		// a couple of errors in the third marker
+		bits[37 + 2 * 132] ^= 1;
+		bits[37 + 2 * 132 + 20] ^= 1;
+
+		let mut sync = FrameSync::new(Marker::ccsds(), 100);
+		let frames: Vec<Frame> = soft(&bits).chunks(13)
+			.flat_map(|chunk| sync.push(chunk))
+			.collect();
+
+		assert_eq!(frames.len(), 5);
+
+		for (i, (frame, data)) in frames.iter().zip(&data).enumerate() {
+			assert_eq!(frame.symbols, soft(data));
+			assert_eq!(frame.offset, 37 + i as u64 * 132);
+		}
+
+		assert_eq!(frames[0].state, SyncState::Verifying(1));
+		assert_eq!(frames[1].state, SyncState::Locked);
+		assert_eq!(frames[2].marker_errors, 2);
+		assert_eq!(frames[2].state, SyncState::Locked);
+	}
+
+	#[test]
+	fn test_flywheel() {
+		let (mut bits, data) = stream(0, 8, 64);
+
+		// wipe out the markers of the third and fourth frames
+		for frame in [2, 3] {
+			for bit in &mut bits[frame * 96..frame * 96 + 32] {
+				*bit ^= 1;
+			}
+		}
+
+		let mut sync = FrameSync::new(Marker::ccsds(), 64);
+		let frames = sync.push(&soft(&bits));
+
+		assert_eq!(frames.len(), 8);
+		assert_eq!(frames[2].state, SyncState::Flywheel(1));
+		assert_eq!(frames[3].state, SyncState::Flywheel(2));
+		assert_eq!(frames[4].state, SyncState::Locked);
+		assert_eq!(frames[3].symbols, soft(&data[3]));
+	}
+
+	#[test]
+	fn test_loses_lock() {
+		let (mut bits, _) = stream(0, 8, 64);
+
+		// the frames slip by 5 symbols after the fourth
+		bits.drain(4 * 96..4 * 96 + 5);
+
+		let mut sync = FrameSync::new(Marker::ccsds(), 64).with_flywheel(1);
+		let frames = sync.push(&soft(&bits));
+
+		let offsets: Vec<u64> = frames.iter().map(|frame| frame.offset).collect();
+		assert_eq!(offsets, [0, 96, 192, 288, 384, 475, 571, 667]);
+
+		// the fifth marker is missed, then the sixth isn't where it should be either
+		assert_eq!(frames[4].state, SyncState::Flywheel(1));
+		assert_eq!(frames[5].state, SyncState::Verifying(1));
+		assert_eq!(frames[6].state, SyncState::Locked);
+	}
+
+	#[test]
+	fn test_encoded_marker() {
+		let code = Code::ccsds();
+		let (bits, data) = stream(11, 4, 80);
+		let symbols = encode_bits(&code, &bits);
+
+		let marker = Marker::ccsds().encoded(&code);
+		assert_eq!(marker.len(), 64);
+
+		// K - 1 bits' worth of symbols depend on the bits before the marker
+		assert_eq!(marker.pattern.iter().filter(|symbol| symbol.is_none()).count(), 12);
+
+		let mut sync = FrameSync::new(marker, 160);
+		let frames = sync.push(&soft(&symbols));
+
+		assert_eq!(frames.len(), 4);
+
+		for (i, frame) in frames.iter().enumerate() {
+			assert_eq!(frame.offset, 2 * (11 + i as u64 * 112));
+			assert_eq!(frame.marker_errors, 0);
+
+			let start = frame.offset as usize + 64;
+			assert_eq!(frame.symbols, soft(&symbols[start..start + 160]));
+		}
+
+		// and the decoded bits can be searched the same as if they hadn't been encoded
+		let decoded = crate::presets::decode_bits(&code, &symbols);
+		let frames = FrameSync::new(Marker::ccsds(), 80).push(&soft(&decoded));
+
+		assert_eq!(frames[1].symbols, soft(&data[1]));
+	}
+
+	#[test]
+	fn test_frames_into_decoder() {
+		use crate::channel::{Awgn, Channel};
+		use crate::prelude::*;
+
+		let code = Code::default();
+		let packets: Vec<Vec<u8>> = (0..4u8)
+			.map(|p| (0..30).map(|x| x * 7 + p).chain([0; 2]).collect())
+			.collect();
+
+		// the marker is sent before each packet's encoded bytes
+		let mut channel = Awgn::new(5.0, 0.5, 3);
+		let mut stream = soft(&random_bits(50, 7));
+
+		for packet in &packets {
+			let encoded = EncoderState::new(code.clone()).push_slice(packet);
+
+			stream.extend(soft(&unpack_bits(&[0x1A, 0xCF, 0xFC, 0x1D])));
+			stream.extend(channel.transmit_soft(&encoded));
+		}
+
+		let mut sync = FrameSync::new(Marker::ccsds(), 32 * 2 * 8);
+		let mut decoder = DecoderState::with_code(&code, 32);
+		let mut output = vec![0; 32];
+
+		let frames = sync.push(&stream);
+		assert_eq!(frames.len(), 4);
+
+		for (frame, packet) in frames.iter().zip(&packets) {
+			decoder.reset();
+			decoder.push_soft_slice(&frame.symbols);
+			decoder.read_into(&mut output);
+
+			assert_eq!(output[..30], packet[..30]);
+		}
+	}
+}
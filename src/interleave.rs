@@ -0,0 +1,272 @@
+//! interleavers, which spread out burst errors so that the decoders see them as scattered errors.
+//!
+//! they work on any `Copy` symbols, so the same interleaver can reorder the bytes output by
+//! [`EncoderState::push_slice`](crate::prelude::EncoderState::push_slice) before sending and the
+//! received bytes afterwards. to deinterleave the soft symbols for interleaved bytes, deinterleave them
+//! 8 at a time as `[i8; 8]`, so each stays with the byte it came from.
+//!
+//! [`Interleaver`] reorders fixed size blocks. [`ConvolutionalInterleaver`] reorders a continuous stream,
+//! which needs half the memory and delay of a block interleaver that spreads bursts as far.
+
+use std::collections::VecDeque;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// reorders blocks of symbols, all the same length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interleaver {
+	/// output `i` of each block is input `permutation[i]`
+	permutation: Vec<usize>,
+}
+
+impl Interleaver {
+	/// an interleaver that puts `permutation[i]` at position `i` of each block.
+	///
+	/// panics if `permutation` isn't a permutation of `0..permutation.len()`
+	pub fn from_permutation(permutation: Vec<usize>) -> Self {
+		assert!(!permutation.is_empty());
+
+		let mut seen = vec![false; permutation.len()];
+		for i in &permutation {
+			assert!(*i < seen.len() && !seen[*i], "not a permutation");
+			seen[*i] = true;
+		}
+
+		Self { permutation }
+	}
+
+	/// symbols are written in row by row and read out column by column, so symbols next to each other in
+	/// the output were `columns` apart in the input and a burst of up to `rows` symbols hits each row at
+	/// most once
+	pub fn block(rows: usize, columns: usize) -> Self {
+		assert!(rows > 0 && columns > 0);
+
+		Self::from_permutation((0..rows * columns)
+			.map(|i| (i % rows) * columns + i / rows)
+			.collect())
+	}
+
+	/// the same as [`Interleaver::block`], but each column is read out starting one row further down than
+	/// the last (wrapping around), so the symbols are read along diagonals. this stops the last symbols of
+	/// one row and the first of the next from ending up near each other
+	pub fn helical(rows: usize, columns: usize) -> Self {
+		assert!(rows > 0 && columns > 0);
+
+		Self::from_permutation((0..rows * columns)
+			.map(|i| {
+				let row = i % rows;
+				let column = (i / rows + row) % columns;
+
+				row * columns + column
+			})
+			.collect())
+	}
+
+	/// a pseudo-random order of `len` symbols, the same for the same `seed`. this doesn't spread bursts
+	/// as evenly, but there's no pattern of errors that it's especially bad at
+	pub fn random(len: usize, seed: u64) -> Self {
+		let mut permutation: Vec<usize> = (0..len).collect();
+		permutation.shuffle(&mut StdRng::seed_from_u64(seed));
+
+		Self::from_permutation(permutation)
+	}
+
+	/// the number of symbols in each block
+	pub fn block_len(&self) -> usize {
+		self.permutation.len()
+	}
+
+	/// reorder `symbols`, which must be a whole number of blocks long
+	pub fn interleave<T: Copy>(&self, symbols: &[T]) -> Vec<T> {
+		self.check_len(symbols.len());
+
+		symbols.chunks_exact(self.block_len())
+			.flat_map(|block| self.permutation.iter().map(|i| block[*i]))
+			.collect()
+	}
+
+	/// put interleaved `symbols` back in their original order
+	pub fn deinterleave<T: Copy>(&self, symbols: &[T]) -> Vec<T> {
+		self.check_len(symbols.len());
+
+		let mut output = symbols.to_vec();
+
+		for (out, block) in output.chunks_exact_mut(self.block_len()).zip(symbols.chunks_exact(self.block_len())) {
+			for (symbol, i) in block.iter().zip(&self.permutation) {
+				out[*i] = *symbol;
+			}
+		}
+
+		output
+	}
+
+	fn check_len(&self, len: usize) {
+		assert!(len.is_multiple_of(self.block_len()), "{len} symbols isn't a whole number of blocks of {}", self.block_len());
+	}
+}
+
+/// a convolutional (Forney) interleaver, which reorders a continuous stream instead of blocks.
+///
+/// symbols are dealt out in turn to `branches` delay lines, the first holding symbols back for 0 turns,
+/// the next for `depth` turns, then `2 * depth` and so on. symbols next to each other in the output were
+/// about `branches * depth` apart in the input. the matching deinterleaver (from
+/// [`ConvolutionalInterleaver::deinterleaver`]) delays them the other way round, so every symbol is delayed
+/// the same in total, see [`ConvolutionalInterleaver::delay`].
+///
+/// the delay lines start full of `T::default()`, which is an erasure for soft symbols
+#[derive(Debug, Clone)]
+pub struct ConvolutionalInterleaver<T> {
+	lines: Vec<VecDeque<T>>,
+	depth: usize,
+	inverse: bool,
+	/// the delay line the next symbol goes to
+	branch: usize,
+}
+
+impl<T: Copy + Default> ConvolutionalInterleaver<T> {
+	pub fn new(branches: usize, depth: usize) -> Self {
+		Self::with_delays(branches, depth, false)
+	}
+
+	/// the deinterleaver that puts the output of this interleaver back in order
+	pub fn deinterleaver(&self) -> Self {
+		Self::with_delays(self.lines.len(), self.depth, !self.inverse)
+	}
+
+	fn with_delays(branches: usize, depth: usize, inverse: bool) -> Self {
+		assert!(branches > 0);
+
+		let lines = (0..branches)
+			.map(|i| {
+				let delay = if inverse { branches - 1 - i } else { i } * depth;
+				VecDeque::from(vec![T::default(); delay])
+			})
+			.collect();
+
+		Self {
+			lines,
+			depth,
+			inverse,
+			branch: 0,
+		}
+	}
+
+	/// how many symbols later each symbol comes out of the deinterleaver than it went into the interleaver
+	pub fn delay(&self) -> usize {
+		let branches = self.lines.len();
+
+		branches * (branches - 1) * self.depth
+	}
+
+	/// push `symbols` through, returning as many symbols as were pushed
+	pub fn push(&mut self, symbols: &[T]) -> Vec<T> {
+		symbols.iter()
+			.map(|symbol| {
+				let branches = self.lines.len();
+				let line = &mut self.lines[self.branch];
+				self.branch = (self.branch + 1) % branches;
+
+				if line.is_empty() {
+					*symbol
+				} else {
+					line.push_back(*symbol);
+					line.pop_front().unwrap()
+				}
+			})
+			.collect()
+	}
+
+	/// fill the delay lines with `T::default()` again, and start back at the first
+	pub fn reset(&mut self) {
+		for line in &mut self.lines {
+			line.iter_mut().for_each(|symbol| *symbol = T::default());
+		}
+
+		self.branch = 0;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_block() {
+		let interleaver = Interleaver::block(2, 3);
+		let symbols: Vec<u8> = (0..12).collect();
+
+		let interleaved = interleaver.interleave(&symbols);
+		assert_eq!(interleaved, [0, 3, 1, 4, 2, 5, 6, 9, 7, 10, 8, 11]);
+		assert_eq!(interleaver.deinterleave(&interleaved), symbols);
+	}
+
+	#[test]
+	fn test_helical() {
+		let interleaver = Interleaver::helical(3, 3);
+		let symbols: Vec<u8> = (0..9).collect();
+
+		let interleaved = interleaver.interleave(&symbols);
+		assert_eq!(interleaved, [0, 4, 8, 1, 5, 6, 2, 3, 7]);
+		assert_eq!(interleaver.deinterleave(&interleaved), symbols);
+	}
+
+	#[test]
+	fn test_random() {
+		let interleaver = Interleaver::random(100, 5);
+		assert_eq!(interleaver, Interleaver::random(100, 5));
+		assert_ne!(interleaver, Interleaver::random(100, 6));
+
+		let symbols: Vec<i8> = (0..100).collect();
+		let interleaved = interleaver.interleave(&symbols);
+
+		assert_ne!(interleaved, symbols);
+		assert_eq!(interleaver.deinterleave(&interleaved), symbols);
+	}
+
+	#[test]
+	#[should_panic(expected = "not a permutation")]
+	fn test_not_a_permutation() {
+		Interleaver::from_permutation(vec![0, 1, 1]);
+	}
+
+	#[test]
+	fn test_convolutional() {
+		let mut interleaver = ConvolutionalInterleaver::new(3, 2);
+		let mut deinterleaver = interleaver.deinterleaver();
+		assert_eq!(deinterleaver.delay(), 12);
+
+		let symbols: Vec<u8> = (1..=60).collect();
+
+		let interleaved = interleaver.push(&symbols);
+		assert_eq!(interleaved[..9], [1, 0, 0, 4, 0, 0, 7, 2, 0]);
+
+		let output = deinterleaver.push(&interleaved);
+		assert_eq!(output[..12], [0; 12]);
+		assert_eq!(output[12..], symbols[..48]);
+
+		// the rest comes out after pushing more
+		assert_eq!(deinterleaver.push(&interleaver.push(&[0; 12])), symbols[48..]);
+
+		interleaver.reset();
+		assert_eq!(interleaver.push(&symbols[..3]), [1, 0, 0]);
+	}
+
+	#[test]
+	fn test_soft_symbols_for_bytes() {
+		let interleaver = Interleaver::block(4, 5);
+		let bytes: Vec<u8> = (0..20).map(|x| x * 13).collect();
+
+		let interleaved = interleaver.interleave(&bytes);
+		let soft: Vec<[i8; 8]> = interleaved.iter()
+			.map(|byte| std::array::from_fn(|i| crate::code::to_soft(byte >> i & 1)))
+			.collect();
+
+		let deinterleaved = interleaver.deinterleave(&soft);
+
+		for (byte, symbols) in bytes.iter().zip(deinterleaved) {
+			let hard = symbols.iter().enumerate().fold(0, |acc, (i, s)| acc | ((*s > 0) as u8) << i);
+			assert_eq!(hard, *byte);
+		}
+	}
+}
@@ -1,86 +1,335 @@
+mod code;
 mod decode;
 mod encode;
 mod common;
+mod poly;
+mod puncture;
+
+pub mod analysis;
+pub mod channel;
+pub mod frame_sync;
+pub mod interleave;
+pub mod node_sync;
+pub mod notation;
+pub mod presets;
+pub mod reed_solomon;
+pub mod scramble;
+pub mod simulate;
 
 pub mod prelude {
+	pub use super::code::Code;
+	pub use super::decode::batch::*;
+	pub use super::decode::Decoder;
+	pub use super::decode::decode_tail_biting;
+	pub use super::decode::{BitDecoderState, RcBitDecoderState, RegisterBitDecoderState};
 	pub use super::decode::DecoderState;
 	pub use super::decode::RcDecoderState;
+	pub use super::decode::RegisterDecoderState;
+	pub use super::decode::{Erasure, FanoDecoder, StackDecoder};
 	pub use super::encode::EncoderState;
+	pub use super::puncture::Puncturer;
 }
 
 #[cfg(test)]
 mod tests {
 	use super::prelude::*;
-	use crate::common::*;
+
+	/// encode then decode `bytes` with every decoder and a few different codes,
+	/// checking that each gets the original data back
+	fn round_trip(bytes: &[u8]) {
+		let codes = [
+			Code::default(),
+			Code::ccsds(),
+			Code::new(5, &[0o25, 0o33, 0o37]),
+		];
+
+		for code in codes {
+			let mut encoder: EncoderState<u8> = EncoderState::new(code.clone());
+			let data_encoded = encoder.push_slice(bytes);
+
+			assert_eq!(bytes, decode(DecoderState::with_code(&code, bytes.len()), &data_encoded), "DecoderState {code:?}");
+			assert_eq!(bytes, decode(RcDecoderState::with_code(&code), &data_encoded), "RcDecoderState {code:?}");
+			assert_eq!(bytes, decode(RegisterDecoderState::with_code(&code), &data_encoded), "RegisterDecoderState {code:?}");
+		}
+	}
 
 	#[test]
-	fn test_round_trip_1() {
-		let bytes = vec![0xFF, 0x10, 0x00];
+	fn test_push_slice_para() {
+		let bytes: Vec<u8> = (0..127).map(|x| x * 2).collect();
 
 		let mut encoder: EncoderState<u8> = EncoderState::default();
 		let data_encoded = encoder.push_slice(&bytes);
 
 		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
+		decoder.push_slice_para(&data_encoded);
+		assert_eq!(bytes, decoder.read());
 
-		assert_eq!(bytes, output);
+		let mut decoder = RcDecoderState::new();
+		decoder.push_slice_para(&data_encoded);
+		assert_eq!(bytes, decoder.read());
 	}
 
 	#[test]
-	fn test_round_trip_full_00() {
-		let bytes = vec![0x00; 127];
+	fn test_inherent_push() {
+		let bytes = [0x12, 0x34, 0xFF, 0x00];
+
+		let mut encoder: EncoderState<u8> = EncoderState::default();
+		let mut decoder = DecoderState::new(bytes.len());
+		let mut rc_decoder = RcDecoderState::new();
+
+		for byte in bytes {
+			let (byte0, byte1) = encoder.push(byte);
+			decoder.push(byte0, byte1);
+			rc_decoder.push_outputs(&[byte0, byte1]);
+		}
+
+		assert_eq!(bytes[..], decoder.read());
+		assert_eq!(bytes[..], rc_decoder.read());
+	}
+
+	#[test]
+	#[should_panic(expected = "whole number")]
+	fn test_push_slice_partial_group() {
+		// the last set of outputs is missing a byte
+		RcDecoderState::new().push_slice(&[0; 5]);
+	}
+
+	#[test]
+	#[should_panic(expected = "whole number")]
+	fn test_push_soft_slice_partial_group() {
+		DecoderState::new(8).push_soft_slice(&[0; 20]);
+	}
+
+	#[test]
+	fn test_early_output() {
+		let bytes: Vec<u8> = (0..2000u32).map(|x| (x * 37 % 251) as u8).collect();
 
 		let mut encoder: EncoderState<u8> = EncoderState::default();
 		let data_encoded = encoder.push_slice(&bytes);
 
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
+		let mut decoder = RcDecoderState::with_early_output();
+		let mut output = Vec::new();
+
+		for chunk in data_encoded.chunks(100) {
+			decoder.push_slice(chunk);
+			output.extend(decoder.settled());
+		}
 
+		assert!(!output.is_empty());
+		assert_eq!(bytes[..output.len()], output);
+
+		output.extend(decoder.read());
 		assert_eq!(bytes, output);
 	}
 
 	#[test]
-	fn test_round_trip_full_ff() {
-		let bytes = vec![0xFF; 127];
+	fn test_reset_and_read_into() {
+		let packets: Vec<Vec<u8>> = vec![
+			vec![0xFF, 0x10, 0x00],
+			vec![0x12, 0x34, 0x56],
+			vec![0xAB, 0xCD, 0xEF],
+		];
+
+		reuse(DecoderState::new(3), &packets);
+		reuse(RcDecoderState::new(), &packets);
+		reuse(RcDecoderState::with_early_output(), &packets);
+		reuse(RegisterDecoderState::new(), &packets);
+	}
+
+	fn reuse(mut decoder: impl Decoder, packets: &[Vec<u8>]) {
+		let mut output = [0xAA; 3];
+
+		for bytes in packets {
+			let mut encoder: EncoderState<u8> = EncoderState::default();
+			let data_encoded = encoder.push_slice(bytes);
+
+			decoder.reset();
+			decoder.push_slice(&data_encoded);
+			decoder.read_into(&mut output);
+
+			assert_eq!(bytes, &output);
+		}
+	}
+
+	#[test]
+	fn test_terminated() {
+		let code = Code::new(5, &[0o23, 0o33]);
+		let mut bytes: Vec<u8> = (0..60u32).map(|x| (x * 37 % 251) as u8).collect();
+		bytes.extend([0; 4]);
+
+		// errors in the last few symbols of every lane make the path to another end state look cheaper
+		let mut data_encoded = EncoderState::new(code.clone()).push_slice(&bytes);
+		let len = data_encoded.len();
+		for i in [len - 1, len - 3, len - 4] {
+			data_encoded[i] ^= 0xFF;
+		}
+
+		fn terminated(mut decoder: impl Decoder, data_encoded: &[u8]) -> Vec<u8> {
+			decoder.set_terminated(true);
+			decode(decoder, data_encoded)
+		}
+
+		assert_ne!(bytes, decode(DecoderState::with_code(&code, bytes.len()), &data_encoded));
+
+		assert_eq!(bytes, terminated(DecoderState::with_code(&code, bytes.len()), &data_encoded), "DecoderState");
+		assert_eq!(bytes, terminated(RcDecoderState::with_code(&code), &data_encoded), "RcDecoderState");
+		assert_eq!(bytes, terminated(RegisterDecoderState::with_code(&code), &data_encoded), "RegisterDecoderState");
+	}
+
+	#[test]
+	fn test_corrects_channel_errors() {
+		use crate::channel::*;
+
+		let code = Code::ccsds();
+
+		// some data, then enough 0s to bring every lane back to state 0. the decoders don't know to end
+		// there though, so only the data is checked
+		let mut bytes: Vec<u8> = (0..300u32).map(|x| (x * 61 % 256) as u8).collect();
+		bytes.extend([0; 6]);
+
+		let mut encoder: EncoderState<u8> = EncoderState::new(code.clone());
+		let data_encoded = encoder.push_slice(&bytes);
+
+		let hard = BinarySymmetric::new(0.01, 7).transmit(&data_encoded);
+		assert_ne!(hard, data_encoded);
+
+		assert_eq!(bytes[..300], decode(DecoderState::with_code(&code, bytes.len()), &hard)[..300], "DecoderState");
+		assert_eq!(bytes[..300], decode(RcDecoderState::with_code(&code), &hard)[..300], "RcDecoderState");
+		assert_eq!(bytes[..300], decode(RegisterDecoderState::with_code(&code), &hard)[..300], "RegisterDecoderState");
+
+		for soft in [
+			Awgn::new(4.0, 0.5, 7).transmit_soft(&data_encoded),
+			BinaryErasure::new(0.2, 7).transmit_soft(&data_encoded),
+		] {
+			assert_eq!(bytes[..300], decode_soft(DecoderState::with_code(&code, bytes.len()), &soft)[..300], "DecoderState");
+			assert_eq!(bytes[..300], decode_soft(RcDecoderState::with_code(&code), &soft)[..300], "RcDecoderState");
+			assert_eq!(bytes[..300], decode_soft(RegisterDecoderState::with_code(&code), &soft)[..300], "RegisterDecoderState");
+		}
+	}
+
+	#[test]
+	fn test_burst_errors_need_interleaving() {
+		use crate::channel::*;
+
+		// 36 rows of 17 encoded bytes
+		const ROWS: usize = 36;
+		const COLUMNS: usize = 17;
+
+		let mut bytes: Vec<u8> = (0..300u32).map(|x| (x * 97 % 256) as u8).collect();
+		bytes.extend([0; 6]);
 
 		let mut encoder: EncoderState<u8> = EncoderState::default();
 		let data_encoded = encoder.push_slice(&bytes);
+		assert_eq!(data_encoded.len(), ROWS * COLUMNS);
 
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
+		// bursts of around 100 symbols (12 bytes) getting half of them wrong, every few thousand symbols
+		let channel = || GilbertElliott::gilbert(0.0005, 0.01, 3);
 
-		assert_eq!(bytes, output);
+		let received = channel().transmit(&data_encoded);
+		assert_ne!(bytes[..300], decode(DecoderState::new(bytes.len()), &received)[..300]);
+
+		// written in row by row and read out column by column, so a burst is spread thinly over all the rows
+		let interleaver = crate::interleave::Interleaver::block(ROWS, COLUMNS);
+		let interleaved = interleaver.interleave(&data_encoded);
+
+		let received = channel().transmit(&interleaved);
+		assert_ne!(received, interleaved);
+
+		let deinterleaved = interleaver.deinterleave(&received);
+
+		assert_eq!(bytes[..300], decode(DecoderState::new(bytes.len()), &deinterleaved)[..300]);
 	}
 
 	#[test]
-	fn test_round_trip_ff_ff_00_00() {
-		let bytes = vec![0xFF, 0xFF, 0x00, 0x00];
+	fn test_scrambled_zeros() {
+		use crate::channel::*;
+		use crate::scramble::AdditiveScrambler;
+
+		// a long run of 0s is sent as a mix of 0s and 1s
+		let mut bytes = AdditiveScrambler::ccsds().scrambled(&[0; 127]);
+		assert!(bytes.iter().filter(|byte| **byte == 0).count() < 10);
+		bytes.extend([0; 2]);
 
 		let mut encoder: EncoderState<u8> = EncoderState::default();
 		let data_encoded = encoder.push_slice(&bytes);
+		let received = BinarySymmetric::new(0.01, 5).transmit(&data_encoded);
 
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
+		let mut decoded = decode(DecoderState::new(bytes.len()), &received);
+		decoded.truncate(127);
+		AdditiveScrambler::ccsds().scramble(&mut decoded);
 
-		assert_eq!(bytes, output);
+		assert_eq!(decoded, [0; 127]);
 	}
 
 	#[test]
-	fn test_round_trip_ff_00_00_00_00() {
-		let bytes = vec![0xFF, 0x00, 0x00, 0x00, 0x00];
+	fn test_concatenated_reed_solomon() {
+		use crate::channel::*;
+		use crate::reed_solomon::ReedSolomon;
+
+		// RS(255, 223) interleaved to depth 4 around the K=7 code
+		let code = ReedSolomon::ccsds(16);
+		let data: Vec<u8> = (0..4 * 223u32).map(|x| (x * 97 % 256) as u8).collect();
+
+		let mut bytes = code.encode_interleaved(&data, 4);
+		let len = bytes.len();
+		bytes.extend([0; 6]);
 
 		let mut encoder: EncoderState<u8> = EncoderState::default();
 		let data_encoded = encoder.push_slice(&bytes);
+		let received = BinarySymmetric::new(0.04, 11).transmit(&data_encoded);
 
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
+		// the viterbi decoder leaves a few bursts of errors, which the RS decoder corrects
+		let mut decoded = decode(DecoderState::new(bytes.len()), &received);
+		decoded.truncate(len);
+		assert_ne!(decoded[..data.len()], data);
 
-		assert_eq!(bytes, output);
+		assert!(code.decode_interleaved(&mut decoded, 4, &[]).is_ok());
+		assert_eq!(decoded[..data.len()], data);
+	}
+
+	fn decode_soft(mut decoder: impl Decoder, symbols: &[i8]) -> Vec<u8> {
+		decoder.push_soft_slice(symbols);
+		decoder.read()
+	}
+
+	fn decode(mut decoder: impl Decoder, data_encoded: &[u8]) -> Vec<u8> {
+		decoder.push_slice(data_encoded);
+		decoder.read()
+	}
+
+	#[test]
+	fn test_round_trip_1() {
+		let bytes = vec![0xFF, 0x10, 0x00];
+
+		round_trip(&bytes);
+	}
+
+	#[test]
+	fn test_round_trip_full_00() {
+		let bytes = vec![0x00; 127];
+
+		round_trip(&bytes);
+	}
+
+	#[test]
+	fn test_round_trip_full_ff() {
+		let bytes = vec![0xFF; 127];
+
+		round_trip(&bytes);
+	}
+
+	#[test]
+	fn test_round_trip_ff_ff_00_00() {
+		let bytes = vec![0xFF, 0xFF, 0x00, 0x00];
+
+		round_trip(&bytes);
+	}
+
+	#[test]
+	fn test_round_trip_ff_00_00_00_00() {
+		let bytes = vec![0xFF, 0x00, 0x00, 0x00, 0x00];
+
+		round_trip(&bytes);
 	}
 
 	#[test]
@@ -91,15 +340,7 @@ mod tests {
 			0b10101010,
 		];
 
-		let mut encoder: EncoderState<u8> = EncoderState::default();
-		let data_encoded = encoder.push_slice(&bytes);
-
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		
-		let output = decoder.read();
-
-		assert_eq!(bytes, output);
+		round_trip(&bytes);
 	}
 
 	#[test]
@@ -115,14 +356,7 @@ mod tests {
 			0b11111111,
 		];
 
-		let mut encoder: EncoderState<u8> = EncoderState::default();
-		let data_encoded = encoder.push_slice(&bytes);
-
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
-
-		assert_eq!(bytes, output);
+		round_trip(&bytes);
 	}
 	
 	#[test]
@@ -138,13 +372,6 @@ mod tests {
 			0b00000001,
 		];
 
-		let mut encoder: EncoderState<u8> = EncoderState::default();
-		let data_encoded = encoder.push_slice(&bytes);
-
-		let mut decoder = DecoderState::new(bytes.len());
-		decoder.push_slice(&data_encoded);
-		let output = decoder.read();
-
-		assert_eq!(bytes, output);
+		round_trip(&bytes);
 	}
 }
@@ -0,0 +1,229 @@
+//! node synchronisation: working out where the symbols for each bit start in a stream of soft symbols, and
+//! undoing the phase the carrier was recovered with, before decoding.
+//!
+//! a receiver can start part way through the symbols for a bit, and a carrier recovery loop can lock on
+//! at any of the phases the modulation looks the same at (180° apart for BPSK, 90° for QPSK). any of these
+//! turns the stream into garbage as far as the decoder can tell, without it saying so. each possibility
+//! is tried by decoding a window of the stream, and the right one is the one the code fits best, where the
+//! path metric grows slowest.
+//!
+//! for a [transparent](Code::is_transparent) code, like the CCSDS one, a 180° phase error just inverts
+//! the decoded bits, so it can't be told apart from no phase error here. that has to be resolved after
+//! decoding, e.g. by finding the sync marker inverted
+
+use crate::channel::Modulation;
+use crate::code::Code;
+use crate::decode::BitDecoderState;
+
+/// the phase error of a recovered carrier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+	Deg0,
+	Deg90,
+	Deg180,
+	Deg270,
+}
+
+impl Rotation {
+	/// the rotations that `modulation` can't tell apart
+	pub fn ambiguities(modulation: Modulation) -> &'static [Rotation] {
+		match modulation {
+			Modulation::Bpsk => &[Rotation::Deg0, Rotation::Deg180],
+			Modulation::Qpsk => &[Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270],
+		}
+	}
+}
+
+/// a way the stream could be lined up, and how well the code fits it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeSync {
+	/// how many symbols to skip so the stream starts with the first symbol for a bit
+	pub offset: usize,
+	/// the phase error of the carrier
+	pub rotation: Rotation,
+	/// the modulation the rotation is of
+	pub modulation: Modulation,
+	/// how much the path metric of the best path grew per symbol more than it had to, from 0 (the path
+	/// agreed with every symbol) to about 1 (it disagreed with every symbol completely)
+	pub metric: f64,
+}
+
+impl NodeSync {
+	/// undo the rotation and skip `offset` symbols, giving symbols that can be pushed into a decoder.
+	///
+	/// `symbols` must start at the same place as the ones this was found from (or a whole number of
+	/// channel uses later)
+	pub fn apply(&self, symbols: &[i8]) -> Vec<i8> {
+		let mut corrected = symbols.to_vec();
+
+		match self.modulation {
+			Modulation::Bpsk => {
+				if self.rotation == Rotation::Deg180 {
+					corrected.iter_mut().for_each(|symbol| *symbol = symbol.saturating_neg());
+				}
+			}
+			Modulation::Qpsk => {
+				for pair in corrected.chunks_exact_mut(2) {
+					let (i, q) = (pair[0], pair[1]);
+
+					// rotate back the other way: received i + jq was sent as (i + jq) e^(-j rotation)
+					[pair[0], pair[1]] = match self.rotation {
+						Rotation::Deg0 => [i, q],
+						Rotation::Deg90 => [q, i.saturating_neg()],
+						Rotation::Deg180 => [i.saturating_neg(), q.saturating_neg()],
+						Rotation::Deg270 => [q.saturating_neg(), i],
+					};
+				}
+			}
+		}
+
+		corrected.drain(..self.offset.min(corrected.len()));
+		corrected
+	}
+}
+
+/// try every offset and rotation on `symbols`, returning how well each fits, best first.
+///
+/// ties (which a transparent code always has, see the module docs) keep the order the rotations are
+/// listed in [`Rotation::ambiguities`], so no rotation wins over one of 180° more
+pub fn node_sync_hypotheses(code: &Code, symbols: &[i8], modulation: Modulation) -> Vec<NodeSync> {
+	let outputs = code.outputs();
+	assert!(symbols.len() >= 16 * outputs, "not enough symbols to find the node sync");
+
+	let mut hypotheses: Vec<NodeSync> = Rotation::ambiguities(modulation).iter()
+		.flat_map(|rotation| (0..outputs).map(move |offset| (*rotation, offset)))
+		.map(|(rotation, offset)| {
+			let mut hypothesis = NodeSync {
+				offset,
+				rotation,
+				modulation,
+				metric: 0.0,
+			};
+
+			let corrected = hypothesis.apply(symbols);
+			let len = corrected.len() - corrected.len() % outputs;
+
+			// the window could start anywhere in the stream
+			let mut decoder = BitDecoderState::with_code(code, len / outputs);
+			decoder.set_any_start(true);
+
+			for each in corrected[..len].chunks_exact(outputs) {
+				decoder.push_soft(each);
+			}
+
+			// even the right path costs something for symbols that aren't as confident as they could be,
+			// which says nothing about how well the code fits
+			let least: u64 = corrected[..len].iter()
+				.map(|symbol| 127 - symbol.unsigned_abs().min(127) as u64)
+				.sum();
+
+			hypothesis.metric = (decoder.path_metric() - least) as f64 / (len as f64 * 254.0);
+			hypothesis
+		})
+		.collect();
+
+	hypotheses.sort_by(|a, b| a.metric.total_cmp(&b.metric));
+	hypotheses
+}
+
+/// find the offset and rotation the code fits `symbols` best with, see [`node_sync_hypotheses`].
+///
+/// a few hundred symbols is usually enough, and more makes it more reliable on a noisy channel
+pub fn node_sync(code: &Code, symbols: &[i8], modulation: Modulation) -> NodeSync {
+	node_sync_hypotheses(code, symbols, modulation)[0]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::channel::{Awgn, Channel};
+	use crate::presets::encode_bits;
+
+	fn bits(len: usize) -> Vec<u8> {
+		let mut state = 0x1234567u32;
+
+		(0..len)
+			.map(|_| {
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+
+				(state & 1) as u8
+			})
+			.collect()
+	}
+
+	/// send `bits` encoded with `code`, starting from bit `start` and symbol `skip` within it
+	fn received(code: &Code, bits: &[u8], skip: usize) -> Vec<i8> {
+		let symbols = encode_bits(code, bits);
+
+		Awgn::new(6.0, 0.5, 9).transmit_bits_soft(&symbols[100 * code.outputs() + skip..])
+	}
+
+	fn rotate(symbols: &[i8], modulation: Modulation, rotation: Rotation) -> Vec<i8> {
+		// applying the opposite correction three times is the same as rotating
+		let sync = NodeSync { offset: 0, rotation, modulation, metric: 0.0 };
+		sync.apply(&sync.apply(&sync.apply(symbols)))
+	}
+
+	#[test]
+	fn test_bpsk() {
+		let code = Code::default();
+		let bits = bits(1000);
+
+		for skip in 0..2 {
+			for rotation in [Rotation::Deg0, Rotation::Deg180] {
+				let symbols = rotate(&received(&code, &bits, skip), Modulation::Bpsk, rotation);
+
+				let sync = node_sync(&code, &symbols[..400], Modulation::Bpsk);
+				assert_eq!((sync.offset, sync.rotation), ((2 - skip) % 2, rotation));
+
+				let corrected = sync.apply(&symbols);
+				let mut decoder = BitDecoderState::with_code(&code, corrected.len() / 2);
+				decoder.set_any_start(true);
+
+				for each in corrected.chunks_exact(2) {
+					decoder.push_soft(each);
+				}
+
+				let decoded = decoder.read(1);
+
+				// past where the decoder might not have started on the right path
+				let start = 100 + skip.div_ceil(2);
+				assert_eq!(decoded[20..800], bits[start + 20..start + 800]);
+			}
+		}
+	}
+
+	#[test]
+	fn test_qpsk() {
+		let code = Code::default();
+		let bits = bits(1000);
+
+		for skip in 0..2 {
+			for rotation in Rotation::ambiguities(Modulation::Qpsk) {
+				let symbols = rotate(&received(&code, &bits, skip), Modulation::Qpsk, *rotation);
+
+				let sync = node_sync(&code, &symbols[..400], Modulation::Qpsk);
+				assert_eq!((sync.offset, sync.rotation), ((2 - skip) % 2, *rotation), "{skip} {rotation:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn test_transparent() {
+		let code = Code::ccsds();
+		let symbols = received(&code, &bits(600), 1);
+
+		let hypotheses = node_sync_hypotheses(&code, &symbols, Modulation::Bpsk);
+
+		// inverted, the stream is still a codeword (of the inverted bits)
+		assert_eq!(hypotheses[0].offset, 1);
+		assert_eq!(hypotheses[0].rotation, Rotation::Deg0);
+		assert_eq!(hypotheses[1].offset, 1);
+		assert_eq!(hypotheses[1].rotation, Rotation::Deg180);
+		assert!((hypotheses[0].metric - hypotheses[1].metric).abs() < 1e-3);
+
+		assert!(hypotheses[2].metric > hypotheses[0].metric * 2.0, "{hypotheses:?}");
+	}
+}
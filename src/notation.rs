@@ -0,0 +1,376 @@
+//! reading and writing codes in the different ways they are written down.
+//!
+//! the same code is written as `(171, 133)` in octal by NASA and 802.11, as `1111001, 1011011` in binary
+//! and as `1 + D + D^2 + D^3 + D^6, 1 + D^2 + D^3 + D^5 + D^6` by GSM and most textbooks. octal and binary
+//! are also written with either end tapping the bit currently being input, so the order has to be given
+//! explicitly with a [`BitOrder`].
+//!
+//! every notation is a list of generators separated by commas, optionally in brackets and optionally
+//! starting with the constraint length (`K=7 (171, 133)`). otherwise K is worked out from the longest
+//! generator. a generator starting with `~` is for an inverted output.
+//!
+//! [`Code`] implements [`Display`](fmt::Display) and [`FromStr`] using octal with the most significant
+//! bit tapping the current input (the way [`Code::new`] takes generators), and always including K.
+//! [`Code::display`] writes it the same way in any other notation, e.g.
+//! `format!("{}", code.display(Notation::Polynomial))`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::code::Code;
+
+/// which end of a generator written as a number taps the bit currently being input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+	/// the most significant bit taps the current input, the way [`Code::new`] takes generators
+	MsbFirst,
+	/// the least significant bit taps the current input, so bit i taps the bit input i steps ago
+	LsbFirst,
+}
+
+/// a way of writing down the generators of a code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+	/// octal numbers, e.g. `171, 133`
+	Octal(BitOrder),
+	/// strings of K 0s and 1s, e.g. `1111001, 1011011`. leading 0s count towards K
+	Binary(BitOrder),
+	/// polynomials in D, where D^i taps the bit input i steps ago, e.g. `1 + D + D^2 + D^3 + D^6`
+	Polynomial,
+}
+
+/// why a code couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseCodeError {
+	/// there were no generators
+	Empty,
+	/// a generator couldn't be read in the notation
+	Generator(String),
+	/// the constraint length couldn't be read, or is out of range
+	ConstraintLength(String),
+	/// there were more than [`Code::MAX_OUTPUTS`] generators
+	TooManyGenerators(usize),
+	/// a generator is 0
+	ZeroGenerator,
+	/// a generator is longer than the constraint length given
+	TooLong(String),
+	/// the code is catastrophic, see [`Code::is_catastrophic`]
+	Catastrophic,
+}
+
+impl fmt::Display for ParseCodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Empty => write!(f, "no generators"),
+			Self::Generator(g) => write!(f, "invalid generator {g:?}"),
+			Self::ConstraintLength(k) => write!(f, "invalid constraint length {k:?}"),
+			Self::TooManyGenerators(n) => write!(f, "{n} generators, but a code can have at most {}", Code::MAX_OUTPUTS),
+			Self::ZeroGenerator => write!(f, "generators can't be 0"),
+			Self::TooLong(g) => write!(f, "generator {g:?} is longer than the constraint length"),
+			Self::Catastrophic => write!(f, "the code is catastrophic"),
+		}
+	}
+}
+
+impl std::error::Error for ParseCodeError {}
+
+/// read a code written in `notation`
+pub fn parse_code(s: &str, notation: Notation) -> Result<Code, ParseCodeError> {
+	let s = s.trim();
+
+	// an optional K=n before the generators
+	let (constraint_len, s) = match s.strip_prefix("K=").or_else(|| s.strip_prefix("k=")) {
+		Some(rest) => {
+			let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+			let k = rest[..end].parse::<u8>()
+				.ok()
+				.filter(|k| (2..=Code::MAX_CONSTRAINT_LEN).contains(k))
+				.ok_or_else(|| ParseCodeError::ConstraintLength(rest[..end].to_string()))?;
+
+			let rest = rest[end..].trim_start();
+			(Some(k as u32), rest.strip_prefix(':').unwrap_or(rest))
+		}
+		None => (None, s),
+	};
+
+	let s = s.trim();
+	let s = s.strip_prefix('(')
+		.and_then(|s| s.strip_suffix(')'))
+		.unwrap_or(s);
+
+	if s.trim().is_empty() {
+		return Err(ParseCodeError::Empty);
+	}
+
+	// each generator as (inverted, its value, which end taps the current input, the least K it needs)
+	let mut parsed = Vec::new();
+
+	for g in s.split(',') {
+		let g = g.trim();
+		let (inverted, g) = match g.strip_prefix('~') {
+			Some(g) => (true, g.trim_start()),
+			None => (false, g),
+		};
+
+		let invalid = || ParseCodeError::Generator(g.to_string());
+
+		let (value, len, order) = match notation {
+			Notation::Octal(order) => {
+				let value = u32::from_str_radix(g, 8).map_err(|_| invalid())?;
+				(value, bit_len(value), order)
+			}
+			Notation::Binary(order) => {
+				if g.is_empty() || g.len() > Code::MAX_CONSTRAINT_LEN as usize {
+					return Err(invalid());
+				}
+
+				let value = u32::from_str_radix(g, 2).map_err(|_| invalid())?;
+				(value, g.len() as u32, order)
+			}
+			Notation::Polynomial => {
+				let value = parse_polynomial(g).ok_or_else(invalid)?;
+				(value, bit_len(value), BitOrder::LsbFirst)
+			}
+		};
+
+		if value == 0 {
+			return Err(ParseCodeError::ZeroGenerator);
+		}
+
+		parsed.push((inverted, value, order, len, g.to_string()));
+	}
+
+	if parsed.len() > Code::MAX_OUTPUTS {
+		return Err(ParseCodeError::TooManyGenerators(parsed.len()));
+	}
+
+	let longest = parsed.iter().map(|(.., len, _)| *len).max().unwrap_or(0).max(2);
+	let constraint_len = constraint_len.unwrap_or(longest);
+
+	if let Some((.., g)) = parsed.iter().find(|(.., len, _)| *len > constraint_len) {
+		return Err(ParseCodeError::TooLong(g.clone()));
+	}
+	if constraint_len > Code::MAX_CONSTRAINT_LEN as u32 {
+		return Err(ParseCodeError::ConstraintLength(constraint_len.to_string()));
+	}
+
+	let generators: Vec<u32> = parsed.iter()
+		.map(|(_, value, order, ..)| match order {
+			BitOrder::MsbFirst => *value,
+			BitOrder::LsbFirst => reverse(*value, constraint_len),
+		})
+		.collect();
+
+	let mut code = Code::allowing_catastrophic(constraint_len as u8, &generators);
+
+	if code.is_catastrophic() {
+		return Err(ParseCodeError::Catastrophic);
+	}
+
+	for (j, (inverted, ..)) in parsed.iter().enumerate() {
+		if *inverted {
+			code = code.with_inverted(j);
+		}
+	}
+
+	Ok(code)
+}
+
+/// write the generators of `code` in `notation`, separated by commas
+pub fn format_code(code: &Code, notation: Notation) -> String {
+	let k = code.constraint_len() as u32;
+
+	let generators: Vec<String> = code.generators().iter()
+		.enumerate()
+		.map(|(j, g)| {
+			let g = match notation {
+				Notation::Octal(BitOrder::MsbFirst) => format!("{g:o}"),
+				Notation::Octal(BitOrder::LsbFirst) => format!("{:o}", reverse(*g, k)),
+				Notation::Binary(BitOrder::MsbFirst) => format!("{g:0width$b}", width = k as usize),
+				Notation::Binary(BitOrder::LsbFirst) => format!("{:0width$b}", reverse(*g, k), width = k as usize),
+				Notation::Polynomial => format_polynomial(reverse(*g, k)),
+			};
+
+			if code.is_inverted(j) {
+				format!("~{g}")
+			} else {
+				g
+			}
+		})
+		.collect();
+
+	generators.join(", ")
+}
+
+/// a code written in a [`Notation`] with `{}`, including K, made by [`Code::display`]
+#[derive(Debug, Clone, Copy)]
+pub struct CodeDisplay<'a> {
+	code: &'a Code,
+	notation: Notation,
+}
+
+impl fmt::Display for CodeDisplay<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "K={} ({})", self.code.constraint_len(), format_code(self.code, self.notation))
+	}
+}
+
+impl Code {
+	/// write the code in `notation`, the same way as [`Code`]'s [`Display`](fmt::Display) does in octal,
+	/// e.g. `K=7 (1 + D + D^2 + D^3 + D^6, 1 + D^2 + D^3 + D^5 + D^6)`
+	pub fn display(&self, notation: Notation) -> CodeDisplay<'_> {
+		CodeDisplay {
+			code: self,
+			notation,
+		}
+	}
+}
+
+impl fmt::Display for Code {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.display(Notation::Octal(BitOrder::MsbFirst)).fmt(f)
+	}
+}
+
+impl FromStr for Code {
+	type Err = ParseCodeError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		parse_code(s, Notation::Octal(BitOrder::MsbFirst))
+	}
+}
+
+fn bit_len(value: u32) -> u32 {
+	32 - value.leading_zeros()
+}
+
+/// reverse the lowest `len` bits of `value`
+fn reverse(value: u32, len: u32) -> u32 {
+	if len == 0 {
+		0
+	} else {
+		value.reverse_bits() >> (32 - len)
+	}
+}
+
+/// read a sum of `1`, `D` and `D^i` terms, bit i being the coefficient of D^i
+fn parse_polynomial(s: &str) -> Option<u32> {
+	let mut value = 0u32;
+
+	for term in s.split('+') {
+		let term: String = term.chars().filter(|c| !c.is_whitespace()).collect();
+
+		let power = match term.as_str() {
+			"1" => 0,
+			"D" | "d" => 1,
+			_ => term.strip_prefix("D^").or_else(|| term.strip_prefix("d^"))?.parse().ok()?,
+		};
+
+		if power >= Code::MAX_CONSTRAINT_LEN as u32 {
+			return None;
+		}
+
+		// terms that appear twice cancel out, which is surely a mistake
+		if value & (1 << power) != 0 {
+			return None;
+		}
+
+		value |= 1 << power;
+	}
+
+	Some(value)
+}
+
+fn format_polynomial(value: u32) -> String {
+	let terms: Vec<String> = (0..32)
+		.filter(|i| value >> i & 1 != 0)
+		.map(|i| match i {
+			0 => "1".to_string(),
+			1 => "D".to_string(),
+			_ => format!("D^{i}"),
+		})
+		.collect();
+
+	terms.join(" + ")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const NOTATIONS: [Notation; 5] = [
+		Notation::Octal(BitOrder::MsbFirst),
+		Notation::Octal(BitOrder::LsbFirst),
+		Notation::Binary(BitOrder::MsbFirst),
+		Notation::Binary(BitOrder::LsbFirst),
+		Notation::Polynomial,
+	];
+
+	#[test]
+	fn test_notations() {
+		let code = Code::new(7, &[0o171, 0o133]);
+
+		assert_eq!(format_code(&code, Notation::Octal(BitOrder::MsbFirst)), "171, 133");
+		assert_eq!(format_code(&code, Notation::Octal(BitOrder::LsbFirst)), "117, 155");
+		assert_eq!(format_code(&code, Notation::Binary(BitOrder::MsbFirst)), "1111001, 1011011");
+		assert_eq!(format_code(&code, Notation::Binary(BitOrder::LsbFirst)), "1001111, 1101101");
+		assert_eq!(format_code(&code, Notation::Polynomial), "1 + D + D^2 + D^3 + D^6, 1 + D^2 + D^3 + D^5 + D^6");
+
+		assert_eq!(parse_code("(117, 155)", Notation::Octal(BitOrder::LsbFirst)), Ok(code.clone()));
+		assert_eq!(parse_code("1 + D+D^2 +D^3 + D^6, 1 + D^2 + D^3 + D^5 + D^6", Notation::Polynomial), Ok(code));
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let codes = [
+			Code::default(),
+			Code::ccsds(),
+			Code::new(5, &[0o25, 0o33, 0o37]),
+			crate::presets::gsm::code(),
+			// doesn't tap the current input, so K has to be given
+			Code::new(4, &[0o7, 0o5]),
+		];
+
+		for code in codes {
+			for notation in NOTATIONS {
+				let written = format!("K={}: ({})", code.constraint_len(), format_code(&code, notation));
+				assert_eq!(parse_code(&written, notation), Ok(code.clone()), "{written}");
+			}
+
+			for notation in NOTATIONS {
+				let written = code.display(notation).to_string();
+				assert_eq!(parse_code(&written, notation), Ok(code.clone()), "{written}");
+			}
+
+			assert_eq!(code.to_string().parse(), Ok(code));
+		}
+	}
+
+	#[test]
+	fn test_display() {
+		assert_eq!(Code::ccsds().to_string(), "K=7 (171, ~133)");
+		assert_eq!(format!("{}", Code::ccsds().display(Notation::Binary(BitOrder::LsbFirst))), "K=7 (1001111, ~1101101)");
+		assert_eq!(
+			format!("{}", crate::presets::gsm::code().display(Notation::Polynomial)),
+			"K=5 (1 + D^3 + D^4, 1 + D + D^3 + D^4)",
+		);
+		assert_eq!("(5, 7)".parse(), Ok(Code::default()));
+
+		// the leading 0s of binary set K
+		assert_eq!(parse_code("0111, 0101", Notation::Binary(BitOrder::MsbFirst)), Ok(Code::new(4, &[0o7, 0o5])));
+	}
+
+	#[test]
+	fn test_errors() {
+		let octal = Notation::Octal(BitOrder::MsbFirst);
+
+		assert_eq!(parse_code("()", octal), Err(ParseCodeError::Empty));
+		assert_eq!(parse_code("171, 139", octal), Err(ParseCodeError::Generator("139".into())));
+		assert_eq!(parse_code("K=3 (17, 5)", octal), Err(ParseCodeError::TooLong("17".into())));
+		assert_eq!(parse_code("K=99 (17, 5)", octal), Err(ParseCodeError::ConstraintLength("99".into())));
+		assert_eq!(parse_code("5, 0", octal), Err(ParseCodeError::ZeroGenerator));
+		assert_eq!(parse_code("6, 5", octal), Err(ParseCodeError::Catastrophic));
+		assert_eq!(parse_code("1 + D^2 + D^2", Notation::Polynomial), Err(ParseCodeError::Generator("1 + D^2 + D^2".into())));
+		assert_eq!("1,1,1,1,1,1,1,1,1".parse::<Code>(), Err(ParseCodeError::TooManyGenerators(9)));
+	}
+}
@@ -0,0 +1,84 @@
+//! arithmetic on polynomials over GF(2), with bit `i` being the coefficient of D^i
+
+/// the degree of `a` (which must not be 0)
+pub(crate) fn degree(a: u64) -> u32 {
+	debug_assert!(a != 0);
+	63 - a.leading_zeros()
+}
+
+pub(crate) fn mul(a: u64, b: u64) -> u64 {
+	assert!(a == 0 || b == 0 || degree(a) + degree(b) < 64, "the product is too long");
+
+	(0..64)
+		.filter(|i| b >> i & 1 != 0)
+		.fold(0, |acc, i| acc ^ (a << i))
+}
+
+/// the quotient and remainder of `a / b`
+pub(crate) fn div_rem(mut a: u64, b: u64) -> (u64, u64) {
+	assert!(b != 0, "division by 0");
+
+	let mut quotient = 0;
+
+	while a != 0 && degree(a) >= degree(b) {
+		let shift = degree(a) - degree(b);
+		quotient |= 1 << shift;
+		a ^= b << shift;
+	}
+
+	(quotient, a)
+}
+
+pub(crate) fn gcd(mut a: u64, mut b: u64) -> u64 {
+	while b != 0 {
+		(a, b) = (b, div_rem(a, b).1);
+	}
+
+	a
+}
+
+/// the gcd of `a` and `b`, and `x` and `y` such that `a x + b y` is the gcd
+pub(crate) fn extended_gcd(a: u64, b: u64) -> (u64, u64, u64) {
+	let (mut old_r, mut r) = (a, b);
+	let (mut old_x, mut x) = (1, 0);
+	let (mut old_y, mut y) = (0, 1);
+
+	while r != 0 {
+		let (quotient, remainder) = div_rem(old_r, r);
+
+		(old_r, r) = (r, remainder);
+		(old_x, x) = (x, old_x ^ mul(quotient, x));
+		(old_y, y) = (y, old_y ^ mul(quotient, y));
+	}
+
+	(old_r, old_x, old_y)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_mul() {
+		// (1 + D)(1 + D) = 1 + D^2
+		assert_eq!(mul(0b11, 0b11), 0b101);
+		assert_eq!(mul(0b1011, 0), 0);
+	}
+
+	#[test]
+	fn test_div_rem() {
+		// 1 + D + D^2 + D^3 = (1 + D)(1 + D^2)
+		assert_eq!(div_rem(0b1111, 0b11), (0b101, 0));
+		assert_eq!(div_rem(0b1000, 0b1011), (0b1, 0b11));
+	}
+
+	#[test]
+	fn test_gcd() {
+		assert_eq!(gcd(0b1111, 0b101), 0b101);
+		assert_eq!(gcd(0b111, 0b101), 1);
+
+		let (d, x, y) = extended_gcd(0b1011, 0b110);
+		assert_eq!(d, 1);
+		assert_eq!(mul(0b1011, x) ^ mul(0b110, y), 1);
+	}
+}
@@ -0,0 +1,80 @@
+//! the convolutional code from CCSDS 131.0-B (TM synchronization and channel coding), which is also
+//! the NASA standard K=7 code.
+//!
+//! G1 = 171 and G2 = 133 (octal), the G1 symbol is sent first and the G2 symbol is inverted.
+//! the encoder isn't terminated, so the last few bits of a stream are less well protected.
+
+use crate::code::Code;
+use crate::common::*;
+
+use super::{decode_bits, encode_bits};
+
+/// the CCSDS code, for use with [`EncoderState`](crate::prelude::EncoderState) and the decoders
+pub fn code() -> Code {
+	Code::ccsds()
+}
+
+/// encode `data`, returning the symbols packed into bytes (twice as many as were input)
+pub fn encode(data: &[u8]) -> Vec<u8> {
+	pack_bits(&encode_bits(&code(), &unpack_bits(data)))
+}
+
+/// decode symbols output by [`encode`] (that may have been corrupted along the way)
+pub fn decode(symbols: &[u8]) -> Vec<u8> {
+	pack_bits(&decode_bits(&code(), &unpack_bits(symbols)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_zeros() {
+		// G1 outputs 0 and the inverted G2 outputs 1, so the output alternates
+		assert_eq!(encode(&[0x00; 4]), [0x55; 8]);
+	}
+
+	#[test]
+	fn test_encoded_asm() {
+		// the attached sync marker 1ACFFC1D encoded from state 0 is usually quoted as 035D49C24FF2686B,
+		// which is the (171, 133) code without the inversion. inverting G2 flips every other symbol
+		let encoded = 0x035D49C24FF2686Bu64 ^ 0x5555555555555555;
+
+		assert_eq!(encode(&[0x1A, 0xCF, 0xFC, 0x1D]), encoded.to_be_bytes());
+	}
+
+	#[test]
+	fn test_ieee80211_signal_field() {
+		// the same code encodes the SIGNAL field of the worked example in annex G of IEEE 802.11a
+		// (annex L of 802.11-2012), 36 Mbit/s and 100 bytes, sent as (133, 171) pairs
+		let signal = [0b10110001, 0b00110000, 0b00000000];
+		let published = "110100011010000100000010001111100111000000000000";
+
+		// CCSDS sends the 171 symbol first and inverts the 133 symbol
+		let expected: Vec<u8> = published.as_bytes()
+			.chunks_exact(2)
+			.flat_map(|pair| [(pair[1] == b'1') as u8, (pair[0] == b'0') as u8])
+			.collect();
+
+		assert_eq!(unpack_bits(&encode(&signal)), expected);
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let data: Vec<u8> = (0..200u32).map(|x| (x * 97 % 256) as u8).collect();
+		assert_eq!(decode(&encode(&data)), data);
+	}
+
+	#[test]
+	fn test_corrects_errors() {
+		let data: Vec<u8> = (0..200u32).map(|x| (x * 41 % 256) as u8).collect();
+		let mut symbols = encode(&data);
+
+		// spread out errors, a few bits each
+		for i in (0..symbols.len() - 8).step_by(20) {
+			symbols[i] ^= 0b00100100;
+		}
+
+		assert_eq!(decode(&symbols), data);
+	}
+}
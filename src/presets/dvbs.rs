@@ -0,0 +1,234 @@
+//! the inner convolutional code from DVB-S (ETSI EN 300 421 section 4.4.3).
+//!
+//! the K=7 mother code (G1 = 171 for X, G2 = 133 for Y) is punctured to one of 5 rates. the symbols are
+//! sent in the order the standard's table 2 gives (e.g. X1 Y1 Y2 X3 for rate 3/4), alternating
+//! between I and Q. mapping them onto I and Q is left to the caller.
+//!
+//! the stream is continuous, so a receiver joining it doesn't know the rate or where the puncturing
+//! pattern starts. [`detect_rate`] finds both by decoding with each in turn.
+
+use crate::code::Code;
+use crate::decode::BitDecoderState;
+use crate::puncture::Puncturer;
+
+use super::encode_bits;
+
+/// the K=7 (171, 133) code, output X (171) first
+pub fn code() -> Code {
+	Code::new(7, &[0o171, 0o133])
+}
+
+/// the coding rates DVB-S uses, all punctured from the rate 1/2 code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rate {
+	Half,
+	TwoThirds,
+	ThreeQuarters,
+	FiveSixths,
+	SevenEighths,
+}
+
+impl Rate {
+	pub const ALL: [Rate; 5] = [
+		Rate::Half,
+		Rate::TwoThirds,
+		Rate::ThreeQuarters,
+		Rate::FiveSixths,
+		Rate::SevenEighths,
+	];
+
+	/// the puncturing pattern for this rate, X on top and Y on the bottom
+	pub fn puncturer(self) -> Puncturer {
+		match self {
+			Rate::Half => Puncturer::new(&[
+				&[1],
+				&[1],
+			]),
+			Rate::TwoThirds => Puncturer::new(&[
+				&[1, 0],
+				&[1, 1],
+			]),
+			Rate::ThreeQuarters => Puncturer::new(&[
+				&[1, 0, 1],
+				&[1, 1, 0],
+			]),
+			Rate::FiveSixths => Puncturer::new(&[
+				&[1, 0, 1, 0, 1],
+				&[1, 1, 0, 1, 0],
+			]),
+			Rate::SevenEighths => Puncturer::new(&[
+				&[1, 0, 0, 0, 1, 0, 1],
+				&[1, 1, 1, 1, 0, 1, 0],
+			]),
+		}
+	}
+}
+
+/// encode `bits` (each 0 or 1) and puncture them to `rate`, returning the symbols (each 0 or 1) in the
+/// order they are sent
+pub fn encode(bits: &[u8], rate: Rate) -> Vec<u8> {
+	rate.puncturer().puncture(&encode_bits(&code(), bits))
+}
+
+/// decode soft symbols punctured to `rate`, where `phase` is how far through the puncturing pattern the
+/// first one is (see [`Puncturer::depuncture_with_phase`]), returning the bits (each 0 or 1).
+///
+/// see [`BitDecoderState::push_soft`] for what the soft symbols mean
+pub fn decode(symbols: &[i8], rate: Rate, phase: usize) -> Vec<u8> {
+	let symbols = rate.puncturer().depuncture_with_phase(symbols, phase);
+
+	decoder_for(&symbols).read(1)
+}
+
+/// a decoder that has had all of `symbols` (already depunctured) pushed into it
+fn decoder_for(symbols: &[i8]) -> BitDecoderState {
+	let code = code();
+
+	// the symbols could have come from anywhere in the stream
+	let mut decoder = BitDecoderState::with_code(&code, symbols.len() / code.outputs());
+	decoder.set_any_start(true);
+
+	for each in symbols.chunks_exact(code.outputs()) {
+		decoder.push_soft(each);
+	}
+
+	decoder
+}
+
+/// what [`detect_rate`] found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+	pub rate: Rate,
+	/// how far through the puncturing pattern the first symbol was
+	pub phase: usize,
+	/// the path metric of the best path per symbol received, from 0 (it matched every symbol
+	/// exactly) to 1 (it disagreed with every symbol completely)
+	pub metric: f64,
+}
+
+/// find the rate and phase a stream of soft symbols was punctured with, by decoding it with each of them
+/// and picking the one with the lowest normalised path metric.
+///
+/// a few hundred symbols is usually enough to tell them apart, and more makes it more reliable on a
+/// noisy channel. the higher rates have so little redundancy that they fit any stream fairly well
+/// (rate 7/8 disagrees with around 2% of the symbols of a rate 5/6 stream), so the detection can't
+/// be trusted once the channel is worse than that
+pub fn detect_rate(symbols: &[i8]) -> Detection {
+	assert!(symbols.len() >= 16, "not enough symbols to detect the rate");
+
+	Rate::ALL.iter()
+		.flat_map(|rate| (0..rate.puncturer().rate().1).map(move |phase| (*rate, phase)))
+		.map(|(rate, phase)| {
+			let depunctured = rate.puncturer().depuncture_with_phase(symbols, phase);
+			let erasures = depunctured.len() - symbols.len();
+
+			// erasures cost the same whichever way they go, so they don't say anything about how good a fit it is
+			let metric = decoder_for(&depunctured).path_metric() - erasures as u64 * 127;
+
+			Detection {
+				rate,
+				phase,
+				metric: metric as f64 / (symbols.len() as f64 * 254.0),
+			}
+		})
+		.min_by(|a, b| a.metric.total_cmp(&b.metric))
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::presets::soft_symbols;
+
+	fn bits(len: usize) -> Vec<u8> {
+		let mut state = 0x2545F491u32;
+
+		(0..len)
+			.map(|_| {
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+
+				(state & 1) as u8
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_symbol_order() {
+		// an impulse gives the generators, so the kept symbols can be picked out
+		let mut impulse = vec![0; 7];
+		impulse[0] = 1;
+
+		let x = [1, 1, 1, 1, 0, 0, 1];
+		let y = [1, 0, 1, 1, 0, 1, 1];
+
+		assert_eq!(encode(&impulse, Rate::Half)[..4], [x[0], y[0], x[1], y[1]]);
+		assert_eq!(encode(&impulse, Rate::ThreeQuarters)[..4], [x[0], y[0], y[1], x[2]]);
+		assert_eq!(
+			encode(&impulse, Rate::SevenEighths),
+			[x[0], y[0], y[1], y[2], y[3], x[4], y[5], x[6]],
+		);
+	}
+
+	#[test]
+	fn test_rates() {
+		let rates: Vec<(usize, usize)> = Rate::ALL.iter().map(|rate| rate.puncturer().rate()).collect();
+		assert_eq!(rates, [(1, 2), (2, 3), (3, 4), (5, 6), (7, 8)]);
+	}
+
+	#[test]
+	fn test_round_trip_with_errors() {
+		let bits = bits(280);
+
+		for rate in Rate::ALL {
+			let mut symbols = soft_symbols(&encode(&bits, rate));
+
+			for i in (20..symbols.len()).step_by(60) {
+				symbols[i] = -symbols[i];
+			}
+
+			// the decoder doesn't know the stream started in state 0, so the start isn't protected as
+			// well as the rest, and neither is the end
+			assert_eq!(decode(&symbols, rate, 0)[20..260], bits[20..260], "{rate:?}");
+		}
+	}
+
+	#[test]
+	fn test_detect_rate() {
+		let bits = bits(600);
+
+		for rate in Rate::ALL {
+			let symbols = soft_symbols(&encode(&bits, rate));
+			let kept = rate.puncturer().rate().1;
+
+			for phase in 0..kept {
+				// join part way through a period
+				let detection = detect_rate(&symbols[phase..phase + 400]);
+
+				assert_eq!((detection.rate, detection.phase), (rate, phase));
+				assert_eq!(detection.metric, 0.0);
+			}
+		}
+	}
+
+	#[test]
+	fn test_detect_rate_with_errors() {
+		let bits = bits(1200);
+		let mut symbols = soft_symbols(&encode(&bits, Rate::FiveSixths));
+
+		// 1% of the symbols are wrong
+		for i in (3..symbols.len()).step_by(100) {
+			symbols[i] = -symbols[i];
+		}
+
+		let detection = detect_rate(&symbols[4..1004]);
+		assert_eq!((detection.rate, detection.phase), (Rate::FiveSixths, 4));
+		assert!((detection.metric - 0.01).abs() < 1e-9);
+
+		let decoded = decode(&symbols[4..1004], detection.rate, detection.phase);
+
+		// symbol 4 is Y4 (X1 Y1 Y2 X3 Y4 X5), so decoding starts from bit 3
+		assert_eq!(decoded[20..800], bits[23..803]);
+	}
+}
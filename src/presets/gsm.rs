@@ -0,0 +1,285 @@
+//! the channel coding for full rate speech traffic channels (TCH/FS) from GSM 05.03 section 3.1.
+//!
+//! the 260 bits of a speech frame are split by importance into 50 class 1a bits, 132 class 1b bits
+//! and 78 class 2 bits. the class 1a bits are protected by 3 parity bits, then the class 1 bits are
+//! reordered, 4 tail bits are added and they are encoded with the K=5 rate 1/2 code
+//! (G0 = 1 + D^3 + D^4, G1 = 1 + D + D^3 + D^4). the class 2 bits are sent unprotected after them,
+//! making 456 coded bits, which are diagonally interleaved over 8 bursts (blocks of 114 bits).
+//!
+//! bits are 0 or 1, and the frames are in the order 05.03 numbers them (d(0) first).
+
+use crate::code::Code;
+use crate::decode::BitDecoderState;
+
+use super::encode_bits;
+
+/// the number of bits in a speech frame
+pub const FRAME_LEN: usize = 260;
+
+/// the number of coded bits a speech frame becomes
+pub const CODED_LEN: usize = 456;
+
+/// the number of coded bits in a block (sent in one burst)
+pub const BLOCK_LEN: usize = 114;
+
+/// the number of class 1a bits, protected by the parity check
+const CLASS_1A_LEN: usize = 50;
+
+/// the number of class 1 bits, protected by the convolutional code
+const CLASS_1_LEN: usize = 182;
+
+const PARITY_LEN: usize = 3;
+
+/// the number of tail bits used to bring the encoder back to state 0
+const TAIL_LEN: usize = 4;
+
+/// the number of bits input to the encoder
+const ENCODED_LEN: usize = CLASS_1_LEN + PARITY_LEN + TAIL_LEN;
+
+/// the K=5 (23, 33) code, output G0 (23) first
+pub fn code() -> Code {
+	Code::new(5, &[0o23, 0o33])
+}
+
+/// the 3 parity bits for the class 1a bits: the remainder of dividing them by g(D) = D^3 + D + 1,
+/// inverted, p(0) first
+pub fn parity(class_1a: &[u8]) -> [u8; PARITY_LEN] {
+	assert_eq!(class_1a.len(), CLASS_1A_LEN);
+
+	// long division, most significant (d(0)) first, then shifting in 3 zeros
+	let remainder = class_1a.iter()
+		.chain(&[0; PARITY_LEN])
+		.fold(0u8, |remainder, bit| {
+			let remainder = (remainder << 1) | (bit & 1);
+
+			if remainder & 0b1000 != 0 {
+				remainder ^ 0b1011
+			} else {
+				remainder
+			}
+		});
+
+	let remainder = !remainder;
+	[(remainder >> 2) & 1, (remainder >> 1) & 1, remainder & 1]
+}
+
+/// the bits input to the encoder: the class 1 bits reordered with the parity bits in the middle,
+/// then the tail bits
+fn reorder(frame: &[u8]) -> Vec<u8> {
+	let mut bits = vec![0; ENCODED_LEN];
+
+	for k in 0..CLASS_1_LEN / 2 {
+		bits[k] = frame[2 * k];
+		bits[CLASS_1_LEN + PARITY_LEN - 1 - k] = frame[2 * k + 1];
+	}
+
+	bits[CLASS_1_LEN / 2..CLASS_1_LEN / 2 + PARITY_LEN].copy_from_slice(&parity(&frame[..CLASS_1A_LEN]));
+
+	bits
+}
+
+/// undo [`reorder`], returning the class 1 bits and the parity bits
+fn unreorder(bits: &[u8]) -> (Vec<u8>, &[u8]) {
+	let mut frame = vec![0; CLASS_1_LEN];
+
+	for k in 0..CLASS_1_LEN / 2 {
+		frame[2 * k] = bits[k];
+		frame[2 * k + 1] = bits[CLASS_1_LEN + PARITY_LEN - 1 - k];
+	}
+
+	(frame, &bits[CLASS_1_LEN / 2..CLASS_1_LEN / 2 + PARITY_LEN])
+}
+
+/// encode a speech frame, returning the 456 coded bits (before interleaving)
+pub fn encode(frame: &[u8]) -> Vec<u8> {
+	assert_eq!(frame.len(), FRAME_LEN);
+
+	let mut coded = encode_bits(&code(), &reorder(frame));
+	coded.extend(&frame[CLASS_1_LEN..]);
+
+	coded
+}
+
+/// decode the soft coded bits of a speech frame (after deinterleaving), returning the frame and whether
+/// the parity check passed. if it didn't, the frame should be treated as bad.
+///
+/// see [`BitDecoderState::push_soft`] for what the soft bits mean
+pub fn decode(symbols: &[i8]) -> (Vec<u8>, bool) {
+	assert_eq!(symbols.len(), CODED_LEN);
+
+	let code = code();
+	let (protected, class_2) = symbols.split_at(ENCODED_LEN * code.outputs());
+
+	// the tail bits bring the encoder back to state 0
+	let mut decoder = BitDecoderState::with_code(&code, ENCODED_LEN);
+	decoder.set_terminated(true);
+
+	for each in protected.chunks_exact(code.outputs()) {
+		decoder.push_soft(each);
+	}
+
+	let bits = decoder.read(1);
+	let (mut frame, parity_bits) = unreorder(&bits);
+	let parity_ok = parity(&frame[..CLASS_1A_LEN]) == parity_bits;
+
+	frame.extend(class_2.iter().map(|symbol| (*symbol > 0) as u8));
+
+	(frame, parity_ok)
+}
+
+/// where coded bit `k` of a frame goes: the block (0 to 7, counting from the first block the frame
+/// is in) and the position in that block
+fn interleaved_position(k: usize) -> (usize, usize) {
+	let block = k % 8;
+	let position = 2 * ((49 * k) % 57) + block / 4;
+
+	(block, position)
+}
+
+/// build the next 4 blocks to send (456 bits, one block after another) from the last frame and the current one.
+///
+/// each frame is spread over 8 blocks, the first 4 sharing them with the previous frame (which uses
+/// the odd positions) and the last 4 sharing them with the next frame (which uses the even positions)
+pub fn interleave<T: Copy + Default>(previous: &[T], current: &[T]) -> Vec<T> {
+	assert_eq!(previous.len(), CODED_LEN);
+	assert_eq!(current.len(), CODED_LEN);
+
+	let mut blocks = vec![T::default(); CODED_LEN];
+
+	for k in 0..CODED_LEN {
+		let (block, position) = interleaved_position(k);
+
+		if block < 4 {
+			blocks[block * BLOCK_LEN + position] = current[k];
+		} else {
+			blocks[(block - 4) * BLOCK_LEN + position] = previous[k];
+		}
+	}
+
+	blocks
+}
+
+/// put a frame back together from the 4 blocks it started in, and the 4 blocks after them (each 456 bits,
+/// one block after another)
+pub fn deinterleave<T: Copy + Default>(previous: &[T], current: &[T]) -> Vec<T> {
+	assert_eq!(previous.len(), CODED_LEN);
+	assert_eq!(current.len(), CODED_LEN);
+
+	(0..CODED_LEN)
+		.map(|k| {
+			let (block, position) = interleaved_position(k);
+
+			if block < 4 {
+				previous[block * BLOCK_LEN + position]
+			} else {
+				current[(block - 4) * BLOCK_LEN + position]
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::presets::soft_symbols;
+
+	/// a made up speech frame, different for each seed
+	fn frame(seed: usize) -> Vec<u8> {
+		(0..FRAME_LEN).map(|i| ((i * 7 + seed + i / 5) % 3 % 2) as u8).collect()
+	}
+
+	#[test]
+	fn test_parity() {
+		// 0 has no remainder, so all the parity bits are 1 once inverted
+		assert_eq!(parity(&[0; CLASS_1A_LEN]), [1, 1, 1]);
+
+		// d(0) is the coefficient of D^52, and D^52 = D^3 = D + 1 mod g(D), so the remainder is
+		// 1 + D and p(0) is what has to be added to make it 1 + D + D^2
+		let mut class_1a = [0; CLASS_1A_LEN];
+		class_1a[0] = 1;
+		assert_eq!(parity(&class_1a), [1, 0, 0]);
+	}
+
+	#[test]
+	fn test_zero_frame() {
+		let coded = encode(&[0; FRAME_LEN]);
+		assert_eq!(coded.len(), CODED_LEN);
+
+		// only the parity bits u(91) to u(93) are 1, and they make c(182) onwards
+		let ones: Vec<usize> = (0..CODED_LEN).filter(|k| coded[*k] == 1).collect();
+		assert_eq!(ones, [182, 183, 184, 186, 188, 194, 195]);
+	}
+
+	#[test]
+	fn test_reordering() {
+		let mut frame = [0; FRAME_LEN];
+		frame[1] = 1; // u(184)
+		frame[181] = 1; // u(94)
+		frame[200] = 1; // class 2, c(378 + 18)
+
+		let bits = reorder(&frame);
+		assert_eq!(bits[184], 1);
+		assert_eq!(bits[94], 1);
+		assert_eq!(bits[CLASS_1_LEN + PARITY_LEN..], [0; TAIL_LEN]);
+
+		assert_eq!(encode(&frame)[378 + 18], 1);
+	}
+
+	#[test]
+	fn test_interleaved_positions() {
+		assert_eq!(interleaved_position(0), (0, 0));
+		assert_eq!(interleaved_position(1), (1, 98));
+		assert_eq!(interleaved_position(4), (4, 51));
+		assert_eq!(interleaved_position(8), (0, 100));
+	}
+
+	#[test]
+	fn test_interleave_round_trip() {
+		let frames: Vec<Vec<u8>> = (0..4).map(|seed| encode(&frame(seed))).collect();
+
+		let blocks: Vec<Vec<u8>> = frames.windows(2)
+			.map(|pair| interleave(&pair[0], &pair[1]))
+			.collect();
+
+		// frame 1 started in the first lot of blocks and finished in the second
+		assert_eq!(deinterleave(&blocks[0], &blocks[1]), frames[1]);
+		assert_eq!(deinterleave(&blocks[1], &blocks[2]), frames[2]);
+	}
+
+	#[test]
+	fn test_corrects_burst_errors() {
+		let frames: Vec<Vec<u8>> = (0..3).map(|seed| encode(&frame(seed))).collect();
+
+		let mut blocks: Vec<Vec<i8>> = frames.windows(2)
+			.map(|pair| soft_symbols(&interleave(&pair[0], &pair[1])))
+			.collect();
+
+		// wipe out most of a burst, which interleaving spreads thinly over two frames
+		for symbol in &mut blocks[1][BLOCK_LEN..BLOCK_LEN + 80] {
+			*symbol = -*symbol;
+		}
+
+		let (decoded, parity_ok) = decode(&deinterleave(&blocks[0], &blocks[1]));
+		assert!(parity_ok);
+
+		// the class 2 bits aren't protected, so only check the class 1 bits
+		assert_eq!(decoded[..CLASS_1_LEN], frame(1)[..CLASS_1_LEN]);
+	}
+
+	#[test]
+	fn test_parity_detects_bad_frames() {
+		let (decoded, parity_ok) = decode(&soft_symbols(&encode(&frame(0))));
+		assert!(parity_ok);
+		assert_eq!(decoded, frame(0));
+
+		// encode a frame with a wrong parity bit, which the decoder will faithfully give back
+		let mut bits = reorder(&frame(0));
+		bits[CLASS_1_LEN / 2] ^= 1;
+
+		let mut coded = encode_bits(&code(), &bits);
+		coded.extend(&frame(0)[CLASS_1_LEN..]);
+
+		let (_, parity_ok) = decode(&soft_symbols(&coded));
+		assert!(!parity_ok);
+	}
+}
@@ -0,0 +1,346 @@
+//! the convolutional coding used for the DATA field of IEEE 802.11a/g/n (OFDM) PPDUs.
+//!
+//! the DATA field is the SERVICE field (16 bits, the first 7 being 0 so the receiver can find the
+//! scrambler state), the PSDU, 6 tail bits and enough pad bits to fill the last OFDM symbol. it is
+//! scrambled, the tail bits are set back to 0 to terminate the code, then it is encoded with the
+//! K=7 (133, 171) code and punctured to the rate being used.
+//!
+//! bits are sent least significant bit first. interleaving and modulation are left to the caller.
+//!
+//! decoding is done with a single [`BitDecoderState`] rather than a
+//! [`DecoderState`](crate::prelude::DecoderState), as the DATA field is one stream of bits and
+//! `DecoderState` decodes 8 independent streams, one in each bit of its bytes. it would have to be given
+//! the coded bits spread out one per byte and would decode 7 empty streams alongside them.
+
+use crate::code::Code;
+use crate::decode::BitDecoderState;
+use crate::puncture::Puncturer;
+use crate::scramble::{AdditiveScrambler, IEEE80211_POLYNOMIAL};
+
+use super::encode_bits;
+
+/// the length of the SERVICE field in bits
+const SERVICE_LEN: usize = 16;
+
+/// the number of tail bits used to bring the encoder back to state 0
+const TAIL_LEN: usize = 6;
+
+/// the K=7 (133, 171) code, output A (133) being sent first
+pub fn code() -> Code {
+	Code::new(7, &[0o133, 0o171])
+}
+
+/// the coding rates 802.11 uses, all punctured from the rate 1/2 code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rate {
+	Half,
+	TwoThirds,
+	ThreeQuarters,
+}
+
+impl Rate {
+	/// the puncturing pattern for this rate
+	pub fn puncturer(self) -> Puncturer {
+		match self {
+			Rate::Half => Puncturer::new(&[
+				&[1],
+				&[1],
+			]),
+			Rate::TwoThirds => Puncturer::new(&[
+				&[1, 1],
+				&[1, 0],
+			]),
+			Rate::ThreeQuarters => Puncturer::new(&[
+				&[1, 1, 0],
+				&[1, 0, 1],
+			]),
+		}
+	}
+}
+
+/// the frame synchronous scrambler, S(x) = x^7 + x^4 + 1. this is
+/// [`AdditiveScrambler::ieee80211`], with what's needed to find the seed from the SERVICE field.
+///
+/// the state is 7 bits, bit 0 being x1 (the newest) and bit 6 being x7
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scrambler {
+	scrambler: AdditiveScrambler,
+}
+
+impl Scrambler {
+	/// create a scrambler starting in `seed`, which must be 7 bits and not 0
+	pub fn new(seed: u8) -> Self {
+		assert!(seed != 0 && seed < 0x80, "the seed must be 7 bits and not 0");
+
+		Self {
+			scrambler: AdditiveScrambler::ieee80211(seed),
+		}
+	}
+
+	/// create a descrambler from the first 7 scrambled bits of the SERVICE field.
+	///
+	/// those bits were 0 before scrambling, so they are the scrambler's output, and the scrambler's state
+	/// is made of its last 7 outputs. the returned scrambler is ready to descramble the 8th bit onwards
+	pub fn from_service(scrambled: &[u8]) -> Self {
+		assert!(scrambled.len() >= 7);
+
+		let state = scrambled[..7].iter()
+			.fold(0, |state, bit| (state << 1) | (bit & 1) as u64);
+
+		Self {
+			scrambler: AdditiveScrambler::from_state(IEEE80211_POLYNOMIAL, state),
+		}
+	}
+
+	/// the next bit of the scrambling sequence
+	pub fn next_bit(&mut self) -> u8 {
+		self.scrambler.next_bit()
+	}
+
+	/// undo `count` steps, recovering the state the scrambler was in before them
+	pub fn rewind(&mut self, count: usize) {
+		self.scrambler.rewind(count);
+	}
+
+	pub fn state(&self) -> u8 {
+		self.scrambler.state() as u8
+	}
+
+	/// scramble (or descramble) bits, each 0 or 1
+	pub fn scramble(&mut self, bits: &mut [u8]) {
+		self.scrambler.scramble_bits(bits);
+	}
+}
+
+/// the number of bits in the DATA field for a PSDU of `psdu_len` bytes, where `data_bits_per_symbol`
+/// is N_DBPS for the data rate being used (e.g. 144 for 36 Mbit/s)
+pub fn data_len(psdu_len: usize, data_bits_per_symbol: usize) -> usize {
+	(SERVICE_LEN + psdu_len * 8 + TAIL_LEN).next_multiple_of(data_bits_per_symbol)
+}
+
+/// build, scramble and encode the DATA field for `psdu`, returning the coded bits (each 0 or 1).
+///
+/// `data_bits_per_symbol` is N_DBPS for the data rate being used (e.g. 144 for 36 Mbit/s)
+pub fn encode(psdu: &[u8], rate: Rate, seed: u8, data_bits_per_symbol: usize) -> Vec<u8> {
+	let mut bits = vec![0; data_len(psdu.len(), data_bits_per_symbol)];
+
+	let tail = SERVICE_LEN + psdu.len() * 8;
+	bits[SERVICE_LEN..tail].copy_from_slice(&unpack_lsb_first(psdu));
+
+	Scrambler::new(seed).scramble(&mut bits);
+	bits[tail..tail + TAIL_LEN].fill(0);
+
+	rate.puncturer().puncture(&encode_bits(&code(), &bits))
+}
+
+/// decode the soft coded bits of a DATA field, returning the PSDU and the scrambler seed that was used.
+///
+/// see [`BitDecoderState::push_soft`] for what the soft bits mean
+pub fn decode(symbols: &[i8], rate: Rate, psdu_len: usize) -> (Vec<u8>, u8) {
+	let symbols = rate.puncturer().depuncture(symbols);
+	let code = code();
+
+	let len = symbols.len() / code.outputs();
+	assert!(len >= SERVICE_LEN + psdu_len * 8 + TAIL_LEN, "not enough symbols for the PSDU");
+
+	let mut decoder = BitDecoderState::with_code(&code, len);
+	for each in symbols.chunks_exact(code.outputs()) {
+		decoder.push_soft(each);
+	}
+
+	let mut bits = decoder.read(1);
+
+	let mut scrambler = Scrambler::from_service(&bits);
+	scrambler.scramble(&mut bits[7..]);
+	scrambler.rewind(bits.len());
+
+	let psdu = pack_lsb_first(&bits[SERVICE_LEN..SERVICE_LEN + psdu_len * 8]);
+
+	(psdu, scrambler.state())
+}
+
+fn unpack_lsb_first(bytes: &[u8]) -> Vec<u8> {
+	bytes.iter()
+		.flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1))
+		.collect()
+}
+
+fn pack_lsb_first(bits: &[u8]) -> Vec<u8> {
+	bits.chunks(8)
+		.map(|chunk| chunk.iter().rev().fold(0, |byte, bit| (byte << 1) | bit))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::presets::soft_symbols;
+
+	/// the message from the worked example in annex G of 802.11a (annex I/L in later versions):
+	/// a MAC header, the start of "Ode to Joy" and the FCS
+	const ANNEX_G_PSDU: &[u8] = b"\x04\x02\x00\x2e\x00\x60\x08\xcd\x37\xa6\x00\x20\xd6\x01\x3c\xf1\x00\x60\x08\xad\x3b\xaf\x00\x00\
+		Joy, bright spark of divinity,\nDaughter of Elysium,\nFire-insired we trea\
+		\x67\x33\x21\xb6";
+
+	/// annex G uses 36 Mbit/s: 16-QAM, rate 3/4, 144 data bits per OFDM symbol
+	const ANNEX_G_DATA_BITS_PER_SYMBOL: usize = 144;
+
+	const ANNEX_G_SEED: u8 = 0b1011101;
+
+	/// the scrambling sequence given in the standard for the all ones initial state
+	const SCRAMBLER_SEQUENCE: &str = "00001110 11110010 11001001 00000010 00100110 00101110 10110110 00001100 \
+		11010100 11100111 10110100 00101010 11111010 01010001 10111000 1111111";
+
+	/// bits written out as 0s and 1s, ignoring whitespace
+	fn bits(s: &str) -> Vec<u8> {
+		s.chars()
+			.filter(|c| !c.is_whitespace())
+			.map(|c| (c == '1') as u8)
+			.collect()
+	}
+
+	#[test]
+	fn test_scrambler_sequence() {
+		let expected = bits(SCRAMBLER_SEQUENCE);
+
+		let mut scrambler = Scrambler::new(0x7F);
+		let sequence: Vec<u8> = (0..127).map(|_| scrambler.next_bit()).collect();
+
+		assert_eq!(sequence, expected);
+
+		// and then it repeats
+		assert_eq!(scrambler.state(), 0x7F);
+	}
+
+	#[test]
+	fn test_scrambler_rewind() {
+		let mut scrambler = Scrambler::new(ANNEX_G_SEED);
+
+		for _ in 0..50 {
+			scrambler.next_bit();
+		}
+
+		scrambler.rewind(50);
+		assert_eq!(scrambler.state(), ANNEX_G_SEED);
+	}
+
+	#[test]
+	fn test_scrambler_from_service() {
+		let mut scrambler = Scrambler::new(ANNEX_G_SEED);
+		let service: Vec<u8> = (0..7).map(|_| scrambler.next_bit()).collect();
+		assert_eq!(Scrambler::from_service(&service).state(), scrambler.state());
+
+		// errors can leave the SERVICE field all 0s, which is a useless state but shouldn't panic
+		assert_eq!(Scrambler::from_service(&[0; 7]).state(), 0);
+	}
+
+	#[test]
+	fn test_lsb_first() {
+		assert_eq!(unpack_lsb_first(&[0b00000110]), [0, 1, 1, 0, 0, 0, 0, 0]);
+		assert_eq!(pack_lsb_first(&[0, 1, 1, 0, 0, 0, 0, 0]), [0b00000110]);
+	}
+
+	#[test]
+	fn test_annex_g_lengths() {
+		assert_eq!(ANNEX_G_PSDU.len(), 100);
+
+		// 6 OFDM symbols of 192 coded bits each
+		assert_eq!(data_len(ANNEX_G_PSDU.len(), ANNEX_G_DATA_BITS_PER_SYMBOL), 864);
+
+		let coded = encode(ANNEX_G_PSDU, Rate::ThreeQuarters, ANNEX_G_SEED, ANNEX_G_DATA_BITS_PER_SYMBOL);
+		assert_eq!(coded.len(), 6 * 192);
+	}
+
+	#[test]
+	fn test_annex_g_signal_field() {
+		// the SIGNAL field of the annex G example (36 Mbit/s, LENGTH 100, both sent LSB first) is encoded at
+		// rate 1/2 without scrambling, which checks the code and the order of outputs A and B
+		let signal = bits("1011 0 001001100000 0 000000");
+		let coded = bits("110100011010000100000010001111100111000000000000");
+
+		assert_eq!(encode_bits(&code(), &signal), coded);
+	}
+
+	#[test]
+	fn test_annex_g_first_symbol() {
+		// the first OFDM symbol of the annex G DATA field, built straight from the standard's description
+		// rather than with anything in this module
+
+		// the SERVICE field then the PSDU, LSB first
+		let mut data = vec![0; SERVICE_LEN];
+		data.extend(ANNEX_G_PSDU.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1)));
+		data.truncate(ANNEX_G_DATA_BITS_PER_SYMBOL);
+
+		// the scrambler's state is its last 7 outputs, so the sequence for a seed carries on from where the
+		// published sequence has just output it
+		let sequence = bits(SCRAMBLER_SEQUENCE).repeat(3);
+		let seed: Vec<u8> = (0..7).rev().map(|i| (ANNEX_G_SEED >> i) & 1).collect();
+		let start = sequence.windows(7).position(|window| window == seed).unwrap() + 7;
+
+		let scrambled = data.iter().zip(&sequence[start..]).map(|(bit, s)| bit ^ s);
+
+		// the encoder drawn in the standard, with register bit i being the input i steps ago, so the
+		// generators are reversed: 133 taps 1101101 and 171 taps 1001111
+		let mut register = 0u32;
+		let pairs: Vec<[u8; 2]> = scrambled
+			.map(|bit| {
+				register = (register << 1 | bit as u32) & 0x7F;
+				[0b1101101, 0b1001111].map(|taps| ((register & taps).count_ones() & 1) as u8)
+			})
+			.collect();
+
+		// bit stealing for rate 3/4 sends A0 B0 A1 B2 from every 3 pairs
+		let expected: Vec<u8> = pairs.chunks_exact(3)
+			.flat_map(|p| [p[0][0], p[0][1], p[1][0], p[2][1]])
+			.collect();
+
+		let coded = encode(ANNEX_G_PSDU, Rate::ThreeQuarters, ANNEX_G_SEED, ANNEX_G_DATA_BITS_PER_SYMBOL);
+		assert_eq!(coded[..192], expected);
+	}
+
+	#[test]
+	fn test_annex_g_round_trip() {
+		let coded = encode(ANNEX_G_PSDU, Rate::ThreeQuarters, ANNEX_G_SEED, ANNEX_G_DATA_BITS_PER_SYMBOL);
+		let (psdu, seed) = decode(&soft_symbols(&coded), Rate::ThreeQuarters, ANNEX_G_PSDU.len());
+
+		assert_eq!(psdu, ANNEX_G_PSDU);
+		assert_eq!(seed, ANNEX_G_SEED);
+	}
+
+	#[test]
+	fn test_service_field_is_scrambling_sequence() {
+		let coded = encode(&[], Rate::Half, ANNEX_G_SEED, 24);
+
+		// with no errors the decoder gives back the scrambled bits, and the SERVICE field was all 0s
+		let symbols = soft_symbols(&coded);
+		let mut decoder = BitDecoderState::with_code(&code(), 24);
+		for each in symbols.chunks_exact(2) {
+			decoder.push_soft(each);
+		}
+
+		let bits = decoder.read(1);
+		let mut scrambler = Scrambler::new(ANNEX_G_SEED);
+		let sequence: Vec<u8> = (0..SERVICE_LEN).map(|_| scrambler.next_bit()).collect();
+
+		assert_eq!(bits[..SERVICE_LEN], sequence);
+	}
+
+	#[test]
+	fn test_all_rates_with_errors() {
+		let psdu: Vec<u8> = (0..60u32).map(|x| (x * 73 % 256) as u8).collect();
+
+		for rate in [Rate::Half, Rate::TwoThirds, Rate::ThreeQuarters] {
+			let coded = encode(&psdu, rate, 0b0101010, 48);
+			let mut symbols = soft_symbols(&coded);
+
+			for i in (10..symbols.len()).step_by(40) {
+				symbols[i] = -symbols[i];
+			}
+
+			let (decoded, seed) = decode(&symbols, rate, psdu.len());
+
+			assert_eq!(decoded, psdu, "{rate:?}");
+			assert_eq!(seed, 0b0101010);
+		}
+	}
+}
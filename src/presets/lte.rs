@@ -0,0 +1,233 @@
+//! the tail biting convolutional code LTE uses for control information (DCI, BCH, UCI on PUCCH/PUSCH),
+//! from 3GPP TS 36.212 sections 5.1.3.1 and 5.1.4.2.
+//!
+//! the K=7 rate 1/3 code (133, 171, 165) starts in the state its last 6 input bits leave it in, so
+//! it needs no tail bits. its 3 output streams are each sub-block interleaved, then put one after
+//! another into a circular buffer that is read from until the required number of bits is reached,
+//! repeating bits or leaving them out to match the rate to the resources available.
+//!
+//! attaching the CRC (and masking it with the RNTI) is left to the caller.
+
+use crate::code::Code;
+use crate::decode::decode_tail_biting;
+use crate::encode::EncoderState;
+
+/// the number of columns of the sub-block interleaver
+const COLUMNS: usize = 32;
+
+/// the permutation applied to the columns of the sub-block interleaver (table 5.1.4-2)
+const COLUMN_PERMUTATION: [usize; COLUMNS] = [
+	1, 17, 9, 25, 5, 21, 13, 29, 3, 19, 11, 27, 7, 23, 15, 31,
+	0, 16, 8, 24, 4, 20, 12, 28, 2, 18, 10, 26, 6, 22, 14, 30,
+];
+
+/// the K=7 (133, 171, 165) code, output d(0) (133) first
+pub fn code() -> Code {
+	Code::new(7, &[0o133, 0o171, 0o165])
+}
+
+/// tail biting encode `bits` (each 0 or 1), returning the symbols (each 0 or 1) output for each bit one
+/// after another, so d(0)_k, d(1)_k and d(2)_k are symbols 3k, 3k + 1 and 3k + 2
+pub fn encode(bits: &[u8]) -> Vec<u8> {
+	// there are no bits to wrap around, and nothing to encode
+	if bits.is_empty() {
+		return Vec::new();
+	}
+
+	let code = code();
+	let bits: Vec<bool> = bits.iter().map(|bit| *bit != 0).collect();
+	let len = bits.len() as isize;
+
+	let mut encoder = EncoderState::<bool>::new(code.clone());
+
+	// the encoder starts in the state the last 6 bits leave it in, wrapping around if there are fewer
+	let memory = code.constraint_len() as isize - 1;
+	for i in len - memory..len {
+		encoder.preload(&[bits[i.rem_euclid(len) as usize]]);
+	}
+
+	encoder.push_slice(&bits)
+		.into_iter()
+		.map(u8::from)
+		.collect()
+}
+
+/// decode the soft symbols for a whole block (in the order [`encode`] outputs them), returning the bits
+/// (each 0 or 1).
+///
+/// see [`BitDecoderState::push_soft`](crate::prelude::BitDecoderState::push_soft) for what the soft symbols mean
+pub fn decode(symbols: &[i8]) -> Vec<u8> {
+	decode_tail_biting(&code(), symbols)
+}
+
+/// the rate matching for a block of `len` bits, done the same way every time for the same length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateMatcher {
+	len: usize,
+	/// where each bit in the circular buffer comes from, as an index into the symbols output by
+	/// [`encode`], without the dummy bits
+	buffer: Vec<usize>,
+}
+
+impl RateMatcher {
+	/// create the rate matching for blocks of `len` bits (`K` in the standard)
+	pub fn new(len: usize) -> Self {
+		assert!(len > 0);
+
+		let outputs = code().outputs();
+
+		let buffer = (0..outputs)
+			.flat_map(|stream| {
+				sub_block_interleave(len)
+					.into_iter()
+					.flatten()
+					.map(move |k| k * outputs + stream)
+			})
+			.collect();
+
+		Self {
+			len,
+			buffer,
+		}
+	}
+
+	/// the number of bits in a block
+	pub fn block_len(&self) -> usize {
+		self.len
+	}
+
+	/// select `count` symbols (`E` in the standard) from the output of [`encode`] to transmit
+	pub fn rate_match<T: Copy>(&self, symbols: &[T], count: usize) -> Vec<T> {
+		assert_eq!(symbols.len(), self.buffer.len());
+
+		self.buffer.iter()
+			.cycle()
+			.take(count)
+			.map(|i| symbols[*i])
+			.collect()
+	}
+
+	/// undo [`RateMatcher::rate_match`] on soft symbols, ready for [`decode`].
+	///
+	/// symbols that were sent more than once are combined, and ones that weren't sent are erasures (0)
+	pub fn rate_dematch(&self, symbols: &[i8]) -> Vec<i8> {
+		let mut sums = vec![0i32; self.buffer.len()];
+
+		for (i, symbol) in self.buffer.iter().cycle().zip(symbols) {
+			sums[*i] += *symbol as i32;
+		}
+
+		sums.into_iter()
+			.map(|sum| sum.clamp(-127, 127) as i8)
+			.collect()
+	}
+}
+
+/// the order the sub-block interleaver outputs one stream of `len` symbols in, as indexes into
+/// the stream with `None` for the dummy bits
+fn sub_block_interleave(len: usize) -> Vec<Option<usize>> {
+	let rows = len.div_ceil(COLUMNS);
+	let dummies = rows * COLUMNS - len;
+
+	// written in row by row after the dummy bits, then read out column by column
+	COLUMN_PERMUTATION.iter()
+		.flat_map(|column| (0..rows).map(move |row| row * COLUMNS + column))
+		.map(|i| i.checked_sub(dummies))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::presets::soft_symbols;
+
+	fn bits(len: usize) -> Vec<u8> {
+		(0..len).map(|i| ((i * 7 + i / 3) % 5 % 2) as u8).collect()
+	}
+
+	#[test]
+	fn test_tail_biting() {
+		let bits = bits(40);
+		let coded = encode(&bits);
+
+		// encoding the block again straight after ends up with the same output, as the start and end states are the same
+		let mut twice = bits.clone();
+		twice.extend(&bits);
+		let mut encoder = EncoderState::<bool>::new(code());
+		let twice: Vec<u8> = encoder.push_slice(&twice.iter().map(|bit| *bit != 0).collect::<Vec<_>>())
+			.into_iter()
+			.map(u8::from)
+			.collect();
+
+		assert_eq!(twice[coded.len()..], coded);
+	}
+
+	#[test]
+	fn test_empty() {
+		assert!(encode(&[]).is_empty());
+		assert!(decode(&[]).is_empty());
+	}
+
+	#[test]
+	fn test_sub_block_interleave() {
+		// a whole row, so no dummy bits and the output is just the permutation
+		let order: Vec<usize> = sub_block_interleave(32).into_iter().flatten().collect();
+		assert_eq!(order, COLUMN_PERMUTATION);
+
+		// 2 rows with 4 dummy bits at the start of the first one. column 1 is read first, which has a
+		// dummy bit then the symbol 32 - 4 + 1 along, then column 17
+		let order = sub_block_interleave(60);
+		assert_eq!(order.len(), 64);
+		assert_eq!(order[..4], [None, Some(29), Some(13), Some(45)]);
+		assert_eq!(order.iter().flatten().count(), 60);
+	}
+
+	#[test]
+	fn test_rate_match_no_repetition() {
+		let matcher = RateMatcher::new(32);
+		let symbols: Vec<usize> = (0..96).collect();
+
+		let matched = matcher.rate_match(&symbols, 96);
+
+		// d(0) interleaved, then d(1), then d(2)
+		assert_eq!(matched[0], COLUMN_PERMUTATION[0] * 3);
+		assert_eq!(matched[32], COLUMN_PERMUTATION[0] * 3 + 1);
+		assert_eq!(matched[64], COLUMN_PERMUTATION[0] * 3 + 2);
+
+		let mut sorted = matched.clone();
+		sorted.sort();
+		assert_eq!(sorted, symbols);
+	}
+
+	#[test]
+	fn test_dematch_combines_repeats() {
+		let matcher = RateMatcher::new(8);
+		let symbols = vec![100; 24 * 2 + 3];
+
+		let dematched = matcher.rate_dematch(&symbols);
+
+		// the first 3 symbols of the buffer were sent 3 times, the others twice
+		assert_eq!(dematched.iter().filter(|s| **s == 127).count(), 24);
+
+		let dematched = matcher.rate_dematch(&[50; 10]);
+		assert_eq!(dematched.iter().filter(|s| **s == 0).count(), 14);
+	}
+
+	#[test]
+	fn test_round_trip_with_errors() {
+		// a DCI format 1A sized block with its CRC
+		let bits = bits(43);
+		let matcher = RateMatcher::new(bits.len());
+
+		// punctured, unchanged and repeated
+		for count in [100, 129, 288] {
+			let mut symbols = soft_symbols(&matcher.rate_match(&encode(&bits), count));
+
+			for i in (5..symbols.len()).step_by(37) {
+				symbols[i] = -symbols[i];
+			}
+
+			assert_eq!(decode(&matcher.rate_dematch(&symbols)), bits, "{count}");
+		}
+	}
+}
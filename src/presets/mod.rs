@@ -0,0 +1,45 @@
+//! ready to use configurations of the encoder and decoders for the codes used by various standards.
+//!
+//! the presets work on a single stream of bits packed into bytes (most significant bit first), which is
+//! how the standards describe them, instead of on 8 streams side by side like [`EncoderState<u8>`]
+//! and the decoders in the prelude do.
+//!
+//! [`EncoderState<u8>`]: crate::prelude::EncoderState
+
+pub mod ccsds;
+pub mod dvbs;
+pub mod gsm;
+pub mod ieee80211;
+pub mod lte;
+
+use crate::code::{self, Code};
+use crate::decode::RcBitDecoderState;
+use crate::encode::EncoderState;
+
+/// encode `bits` (each 0 or 1) with `code` starting from state 0, returning the symbols (each 0 or 1)
+/// output for each bit one after another
+pub(crate) fn encode_bits(code: &Code, bits: &[u8]) -> Vec<u8> {
+	let bits: Vec<bool> = bits.iter().map(|bit| *bit != 0).collect();
+
+	EncoderState::<bool>::new(code.clone())
+		.push_slice(&bits)
+		.into_iter()
+		.map(u8::from)
+		.collect()
+}
+
+/// decode symbols (each 0 or not 0) output by [`encode_bits`], returning the bits (each 0 or 1)
+pub(crate) fn decode_bits(code: &Code, symbols: &[u8]) -> Vec<u8> {
+	let mut decoder = RcBitDecoderState::with_code(code, 1);
+
+	for each in symbols.chunks_exact(code.outputs()) {
+		decoder.push(each);
+	}
+
+	decoder.read()
+}
+
+/// turn hard bits (each 0 or not 0) into soft symbols for the decoders, as confident as they can be
+pub fn soft_symbols(bits: &[u8]) -> Vec<i8> {
+	bits.iter().map(|bit| code::to_soft(*bit)).collect()
+}
@@ -0,0 +1,156 @@
+/// raises the rate of a code by not transmitting some of the symbols the encoder outputs.
+///
+/// the pattern has one row per output of the code, and one column per bit input. a 1 means the
+/// symbol is transmitted and a 0 means it is left out. symbols are transmitted in the order they are
+/// output, column by column.
+///
+/// on the receiving end, [`Puncturer::depuncture`] puts erasures back where symbols were left out
+/// so the soft symbols can be pushed into a decoder as normal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puncturer {
+	outputs: usize,
+	/// indexed by `column * outputs + row`
+	pattern: Vec<bool>,
+	kept: usize,
+}
+
+impl Puncturer {
+	/// create a puncturer from its pattern, one row per output of the code
+	pub fn new(pattern: &[&[u8]]) -> Self {
+		assert!(!pattern.is_empty());
+
+		let outputs = pattern.len();
+		let period = pattern[0].len();
+
+		assert!(period > 0);
+		assert!(pattern.iter().all(|row| row.len() == period), "every row of the pattern must be the same length");
+
+		let pattern: Vec<bool> = (0..period * outputs)
+			.map(|i| pattern[i % outputs][i / outputs] != 0)
+			.collect();
+
+		let kept = pattern.iter().filter(|keep| **keep).count();
+		assert!(kept > 0, "the pattern must keep at least one symbol");
+
+		Self {
+			outputs,
+			pattern,
+			kept,
+		}
+	}
+
+	/// the number of outputs of the code this punctures
+	pub fn outputs(&self) -> usize {
+		self.outputs
+	}
+
+	/// the number of bits input before the pattern repeats
+	pub fn period(&self) -> usize {
+		self.pattern.len() / self.outputs
+	}
+
+	/// the rate of the punctured code, as (bits input, symbols transmitted) per period
+	pub fn rate(&self) -> (usize, usize) {
+		(self.period(), self.kept)
+	}
+
+	/// leave out the symbols the pattern says to
+	pub fn puncture<T: Copy>(&self, symbols: &[T]) -> Vec<T> {
+		symbols.iter()
+			.zip(self.pattern.iter().cycle())
+			.filter(|(_, keep)| **keep)
+			.map(|(symbol, _)| *symbol)
+			.collect()
+	}
+
+	/// put erasures (0) back in where symbols were left out.
+	///
+	/// the output always ends on a whole bit's worth of symbols, padding with erasures if needed
+	pub fn depuncture(&self, symbols: &[i8]) -> Vec<i8> {
+		self.depuncture_with_phase(symbols, 0)
+	}
+
+	/// the same as [`Puncturer::depuncture`], but for when `symbols` doesn't start at the start of the
+	/// pattern. `phase` is how many of the symbols the pattern keeps came before the first one.
+	///
+	/// if that is part way through a bit, the output starts with erasures for the symbols of that bit
+	/// that were missed
+	pub fn depuncture_with_phase(&self, symbols: &[i8], phase: usize) -> Vec<i8> {
+		assert!(phase < self.kept, "the phase must be less than the number of symbols kept per period");
+
+		// where in the pattern the first symbol is, and where the bit it's part of starts
+		let start = self.pattern.iter()
+			.enumerate()
+			.filter(|(_, keep)| **keep)
+			.nth(phase)
+			.unwrap().0;
+
+		let mut ans = Vec::with_capacity(symbols.len() * self.pattern.len() / self.kept + self.outputs * 2);
+		ans.resize(start % self.outputs, 0);
+
+		let mut symbols = symbols.iter();
+		let mut end = 0;
+
+		for keep in self.pattern.iter().cycle().skip(start) {
+			if *keep {
+				match symbols.next() {
+					Some(symbol) => ans.push(*symbol),
+					None => break,
+				}
+
+				end = ans.len();
+			} else {
+				ans.push(0);
+			}
+		}
+
+		// only keep the erasures needed to finish the last bit
+		ans.truncate(end);
+		ans.resize(end.next_multiple_of(self.outputs), 0);
+
+		ans
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn three_quarters() -> Puncturer {
+		Puncturer::new(&[
+			&[1, 1, 0],
+			&[1, 0, 1],
+		])
+	}
+
+	#[test]
+	fn test_rate() {
+		assert_eq!(three_quarters().rate(), (3, 4));
+		assert_eq!(three_quarters().period(), 3);
+	}
+
+	#[test]
+	fn test_puncture() {
+		// A0 B0 A1 B1 A2 B2 -> A0 B0 A1 B2
+		let symbols = [10, 11, 20, 21, 30, 31, 40, 41];
+		assert_eq!(three_quarters().puncture(&symbols), [10, 11, 20, 31, 40, 41]);
+	}
+
+	#[test]
+	fn test_depuncture() {
+		let symbols = [10, 11, 20, 31, 40, 41];
+		assert_eq!(three_quarters().depuncture(&symbols), [10, 11, 20, 0, 0, 31, 40, 41]);
+
+		// ends half way through a bit
+		assert_eq!(three_quarters().depuncture(&[10, 11, 20]), [10, 11, 20, 0]);
+	}
+
+	#[test]
+	fn test_depuncture_with_phase() {
+		// starting from A1
+		assert_eq!(three_quarters().depuncture_with_phase(&[20, 31, 40, 41], 2), [20, 0, 0, 31, 40, 41]);
+
+		// starting from B2, so A2 is missing
+		assert_eq!(three_quarters().depuncture_with_phase(&[31, 40, 41], 3), [0, 31, 40, 41]);
+	}
+}
@@ -0,0 +1,686 @@
+//! Reed–Solomon codes over GF(256), for use as the outer code of a concatenated system.
+//!
+//! the classic deep space system is RS(255, 223) around the K=7 convolutional code, with the RS
+//! codewords interleaved so that the bursts of errors the Viterbi decoder makes when it goes wrong are
+//! shared between them. the bytes read out of a decoder (e.g. [`DecoderState`](crate::prelude::DecoderState))
+//! can be given straight to [`ReedSolomon::decode_interleaved`].
+//!
+//! a codeword is the data followed by the parity, with the first byte being the coefficient of the
+//! highest power of x. codewords shorter than 255 bytes are shortened codes, as if the data were padded
+//! with 0s at the start.
+
+use std::fmt;
+
+/// the largest number of bytes in a codeword
+pub const MAX_LEN: usize = 255;
+
+/// the matrix taking the conventional representation of a symbol to the dual basis CCSDS uses, one
+/// row per bit of the dual basis representation (CCSDS 131.0-B annex F)
+const DUAL_BASIS: [u8; 8] = [0x8D, 0xEF, 0xEC, 0x86, 0xFA, 0x99, 0xAF, 0x7B];
+
+/// the error when a codeword has more errors than can be corrected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uncorrectable;
+
+impl fmt::Display for Uncorrectable {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "too many errors to correct")
+	}
+}
+
+impl std::error::Error for Uncorrectable {}
+
+/// GF(256), as powers of a primitive element α
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Field {
+	/// α^i, twice over so that adding two logs doesn't need reducing
+	exp: Vec<u8>,
+	/// log_α(x), with 0 for 0 (which has no log)
+	log: Vec<u8>,
+}
+
+impl Field {
+	fn new(polynomial: u16) -> Self {
+		assert!(polynomial >> 8 == 1, "the field polynomial must have degree 8");
+
+		let mut exp = vec![0; 2 * MAX_LEN];
+		let mut log = vec![0; 256];
+		let mut x = 1u16;
+
+		for i in 0..MAX_LEN {
+			assert!(i == 0 || x != 1, "the field polynomial must be primitive");
+
+			exp[i] = x as u8;
+			exp[i + MAX_LEN] = x as u8;
+			log[x as usize] = i as u8;
+
+			x <<= 1;
+			if x & 0x100 != 0 {
+				x ^= polynomial;
+			}
+		}
+
+		assert!(x == 1, "the field polynomial must be primitive");
+
+		Self { exp, log }
+	}
+
+	/// α^power, for any power
+	fn pow(&self, power: usize) -> u8 {
+		self.exp[power % MAX_LEN]
+	}
+
+	fn mul(&self, a: u8, b: u8) -> u8 {
+		if a == 0 || b == 0 {
+			0
+		} else {
+			self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+		}
+	}
+
+	fn div(&self, a: u8, b: u8) -> u8 {
+		assert!(b != 0, "division by 0");
+
+		if a == 0 {
+			0
+		} else {
+			self.exp[self.log[a as usize] as usize + MAX_LEN - self.log[b as usize] as usize]
+		}
+	}
+
+	/// a^n, for a non-zero `a`
+	fn power(&self, a: u8, n: usize) -> u8 {
+		self.pow(self.log[a as usize] as usize * n)
+	}
+
+	fn inv(&self, a: u8) -> u8 {
+		self.div(1, a)
+	}
+
+	/// evaluate a polynomial (coefficients lowest power first) at `x`
+	fn eval(&self, polynomial: &[u8], x: u8) -> u8 {
+		polynomial.iter().rev().fold(0, |acc, c| self.mul(acc, x) ^ c)
+	}
+}
+
+/// lookup tables between the conventional representation and the dual basis
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DualBasis {
+	to_dual: Vec<u8>,
+	from_dual: Vec<u8>,
+}
+
+impl DualBasis {
+	fn new() -> Self {
+		// bit i of x selects row 7 - i of the matrix
+		let to_dual: Vec<u8> = (0..256)
+			.map(|x| (0..8)
+				.filter(|i| x >> i & 1 != 0)
+				.fold(0, |acc, i| acc ^ DUAL_BASIS[7 - i]))
+			.collect();
+
+		let mut from_dual = vec![0; 256];
+		for (x, dual) in to_dual.iter().enumerate() {
+			from_dual[*dual as usize] = x as u8;
+		}
+
+		Self { to_dual, from_dual }
+	}
+}
+
+/// a Reed–Solomon code with `parity` parity bytes per codeword, which can correct any `e` errors and `f`
+/// erasures (errors where they are known to be) as long as `2e + f <= parity`.
+///
+/// the generator's roots are α^(gap * (first + i)) for i in 0..parity, where α is a root of the field
+/// polynomial
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReedSolomon {
+	field: Field,
+	parity: usize,
+	first_root: usize,
+	root_gap: usize,
+	/// the generator polynomial, lowest power first
+	generator: Vec<u8>,
+	dual_basis: Option<DualBasis>,
+}
+
+impl ReedSolomon {
+	/// a code with `parity` parity bytes, over the field with polynomial x^8 + x^4 + x^3 + x^2 + 1
+	/// (0x11D) and the generator's roots starting from α^0
+	pub fn new(parity: usize) -> Self {
+		assert!(parity > 0 && parity < MAX_LEN, "the number of parity bytes must be between 1 and 254");
+
+		let mut code = Self {
+			field: Field::new(0x11D),
+			parity,
+			first_root: 0,
+			root_gap: 1,
+			generator: Vec::new(),
+			dual_basis: None,
+		};
+
+		code.build_generator();
+		code
+	}
+
+	/// the CCSDS code (CCSDS 131.0-B) correcting `correctable` errors, which must be 8 or 16 for
+	/// RS(255, 239) or RS(255, 223). symbols are in the dual basis
+	pub fn ccsds(correctable: usize) -> Self {
+		assert!(correctable == 8 || correctable == 16, "CCSDS codes correct 8 or 16 errors");
+
+		Self::new(2 * correctable)
+			.with_field(0x187)
+			.with_roots(128 - correctable, 11)
+			.with_dual_basis()
+	}
+
+	/// use the field with the primitive polynomial `polynomial`, with bit i being the coefficient of x^i
+	/// (e.g. 0x187 for x^8 + x^7 + x^2 + x + 1)
+	pub fn with_field(mut self, polynomial: u16) -> Self {
+		self.field = Field::new(polynomial);
+		self.build_generator();
+		self
+	}
+
+	/// make the generator's roots α^(gap * (first + i)). the gap must not share a factor with 255, so that
+	/// α^gap is primitive
+	pub fn with_roots(mut self, first: usize, gap: usize) -> Self {
+		assert!(gap > 0 && gcd(gap, MAX_LEN) == 1, "the gap between roots must not share a factor with 255");
+
+		self.first_root = first;
+		self.root_gap = gap;
+		self.build_generator();
+		self
+	}
+
+	/// take and give symbols in the dual basis CCSDS uses, instead of the conventional representation
+	pub fn with_dual_basis(mut self) -> Self {
+		self.dual_basis = Some(DualBasis::new());
+		self
+	}
+
+	/// the number of parity bytes in each codeword
+	pub fn parity(&self) -> usize {
+		self.parity
+	}
+
+	/// the most data a codeword can hold
+	pub fn max_data_len(&self) -> usize {
+		MAX_LEN - self.parity
+	}
+
+	/// the root α^(gap * (first + i))
+	fn root(&self, i: usize) -> u8 {
+		self.field.pow(self.root_gap * (self.first_root + i))
+	}
+
+	fn build_generator(&mut self) {
+		let mut generator = vec![1];
+
+		for i in 0..self.parity {
+			// multiply by (x - root), which is (x + root)
+			let root = self.root(i);
+			let mut next = vec![0; generator.len() + 1];
+
+			for (j, c) in generator.iter().enumerate() {
+				next[j + 1] ^= c;
+				next[j] ^= self.field.mul(*c, root);
+			}
+
+			generator = next;
+		}
+
+		self.generator = generator;
+	}
+
+	/// encode `data` (at most [`ReedSolomon::max_data_len`] bytes), returning the codeword
+	pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+		assert!(data.len() <= self.max_data_len(), "too much data for one codeword");
+
+		let mut codeword = data.to_vec();
+		self.convert_from_dual(&mut codeword);
+
+		// the remainder of data * x^parity divided by the generator, by long division
+		let mut remainder = vec![0; self.parity];
+
+		for byte in &codeword {
+			let factor = byte ^ remainder[0];
+			remainder.rotate_left(1);
+			remainder[self.parity - 1] = 0;
+
+			for (r, g) in remainder.iter_mut().zip(self.generator[..self.parity].iter().rev()) {
+				*r ^= self.field.mul(factor, *g);
+			}
+		}
+
+		codeword.extend(remainder);
+		self.convert_to_dual(&mut codeword);
+		codeword
+	}
+
+	/// correct the errors in `codeword`, given the positions of any bytes known to be wrong (`erasures`),
+	/// returning the number of bytes corrected.
+	///
+	/// if there are too many errors `codeword` is left as it is. too many errors can also look like a
+	/// different codeword with only a few, in which case it is "corrected" to that, but with 16 or more
+	/// parity bytes this is very unlikely
+	pub fn decode(&self, codeword: &mut [u8], erasures: &[usize]) -> Result<usize, Uncorrectable> {
+		let len = codeword.len();
+		assert!(len > self.parity && len <= MAX_LEN, "a codeword must be longer than the parity and at most 255 bytes");
+		assert!(erasures.iter().all(|i| *i < len), "erasures must be in the codeword");
+
+		let mut received = codeword.to_vec();
+		self.convert_from_dual(&mut received);
+
+		let syndromes = self.syndromes(&received);
+		if syndromes.iter().all(|s| *s == 0) {
+			return Ok(0);
+		}
+
+		let mut erasures = erasures.to_vec();
+		erasures.sort_unstable();
+		erasures.dedup();
+
+		if erasures.len() > self.parity {
+			return Err(Uncorrectable);
+		}
+
+		// byte i is the coefficient of x^(len - 1 - i), so an error there has locator β^(len - 1 - i),
+		// where β = α^gap
+		let locator = |i: usize| self.field.pow(self.root_gap * (len - 1 - i));
+
+		let lambda = self.error_locator(&syndromes, erasures.iter().map(|i| locator(*i)));
+		let errors = lambda.len() - 1;
+
+		// find the errors by trying every position (chien search)
+		let positions: Vec<usize> = (0..len)
+			.filter(|i| self.field.eval(&lambda, self.field.inv(locator(*i))) == 0)
+			.collect();
+
+		if positions.len() != errors {
+			return Err(Uncorrectable);
+		}
+
+		// the error evaluator, omega = syndromes * lambda mod x^parity
+		let mut omega = vec![0; self.parity];
+		for (i, s) in syndromes.iter().enumerate() {
+			for (j, l) in lambda.iter().enumerate().take(self.parity - i) {
+				omega[i + j] ^= self.field.mul(*s, *l);
+			}
+		}
+
+		// the formal derivative of lambda, which only has the odd powers
+		let derivative: Vec<u8> = lambda.iter()
+			.enumerate()
+			.skip(1)
+			.map(|(i, l)| if i % 2 == 1 { *l } else { 0 })
+			.collect();
+
+		// the error values (forney)
+		for i in &positions {
+			let x = locator(*i);
+			let x_inv = self.field.inv(x);
+
+			// X^(1 - first root)
+			let scale = self.field.power(x, MAX_LEN + 1 - self.first_root % MAX_LEN);
+			let numerator = self.field.mul(self.field.eval(&omega, x_inv), scale);
+			let denominator = self.field.eval(&derivative, x_inv);
+
+			if denominator == 0 {
+				return Err(Uncorrectable);
+			}
+
+			received[*i] ^= self.field.div(numerator, denominator);
+		}
+
+		// it should be a codeword now, unless there were too many errors in a way that fooled the decoder
+		if self.syndromes(&received).iter().any(|s| *s != 0) {
+			return Err(Uncorrectable);
+		}
+
+		self.convert_to_dual(&mut received);
+		codeword.copy_from_slice(&received);
+
+		Ok(positions.len())
+	}
+
+	/// encode `data` as `depth` interleaved codewords, the way CCSDS does: byte j of the data goes in
+	/// codeword j % depth, and the parity is interleaved the same way after it.
+	///
+	/// `data` must be a multiple of `depth` long, and at most `depth` times [`ReedSolomon::max_data_len`]
+	pub fn encode_interleaved(&self, data: &[u8], depth: usize) -> Vec<u8> {
+		assert!((1..=8).contains(&depth), "the interleaving depth must be from 1 to 8");
+		assert!(data.len().is_multiple_of(depth), "the data must be a whole number of bytes per codeword");
+
+		let codewords: Vec<Vec<u8>> = (0..depth)
+			.map(|i| {
+				let data: Vec<u8> = data.iter().skip(i).step_by(depth).copied().collect();
+				self.encode(&data)
+			})
+			.collect();
+
+		(0..codewords[0].len() * depth)
+			.map(|j| codewords[j % depth][j / depth])
+			.collect()
+	}
+
+	/// correct the errors in `depth` interleaved codewords (as made by [`ReedSolomon::encode_interleaved`]),
+	/// given the positions of any bytes known to be wrong, returning the number of bytes corrected.
+	///
+	/// every codeword has to be corrected for this to succeed, but the ones that can be are corrected
+	/// either way
+	pub fn decode_interleaved(&self, block: &mut [u8], depth: usize, erasures: &[usize]) -> Result<usize, Uncorrectable> {
+		assert!((1..=8).contains(&depth), "the interleaving depth must be from 1 to 8");
+		assert!(block.len().is_multiple_of(depth), "the block must be a whole number of bytes per codeword");
+
+		let mut corrected = 0;
+		let mut result = Ok(());
+
+		for i in 0..depth {
+			let mut codeword: Vec<u8> = block.iter().skip(i).step_by(depth).copied().collect();
+			let codeword_erasures: Vec<usize> = erasures.iter()
+				.filter(|j| *j % depth == i)
+				.map(|j| j / depth)
+				.collect();
+
+			match self.decode(&mut codeword, &codeword_erasures) {
+				Ok(count) => corrected += count,
+				Err(error) => result = Err(error),
+			}
+
+			for (j, byte) in codeword.into_iter().enumerate() {
+				block[j * depth + i] = byte;
+			}
+		}
+
+		result.map(|_| corrected)
+	}
+
+	/// the received polynomial evaluated at each root of the generator
+	fn syndromes(&self, received: &[u8]) -> Vec<u8> {
+		(0..self.parity)
+			.map(|i| {
+				let root = self.root(i);
+				received.iter().fold(0, |acc, byte| self.field.mul(acc, root) ^ byte)
+			})
+			.collect()
+	}
+
+	/// the error locator polynomial (lowest power first, trimmed to its degree), found with
+	/// berlekamp-massey starting from the erasures' locator
+	fn error_locator(&self, syndromes: &[u8], erasures: impl Iterator<Item = u8>) -> Vec<u8> {
+		let field = &self.field;
+
+		// the product of (1 - X x) for each erasure's locator X
+		let mut lambda = vec![0; self.parity + 1];
+		lambda[0] = 1;
+		let mut erased = 0;
+
+		for x in erasures {
+			for j in (1..=erased + 1).rev() {
+				lambda[j] ^= field.mul(x, lambda[j - 1]);
+			}
+
+			erased += 1;
+		}
+
+		let mut b = lambda.clone();
+		let mut len = erased;
+
+		for r in erased..self.parity {
+			let discrepancy = (0..=r)
+				.fold(0, |acc, i| acc ^ field.mul(lambda[i], syndromes[r - i]));
+
+			// shift b along one (multiplying it by x)
+			b.rotate_right(1);
+			b[0] = 0;
+
+			if discrepancy == 0 {
+				continue;
+			}
+
+			let next: Vec<u8> = lambda.iter()
+				.zip(&b)
+				.map(|(l, b)| l ^ field.mul(discrepancy, *b))
+				.collect();
+
+			if 2 * len <= r + erased {
+				len = r + 1 + erased - len;
+				b = lambda.iter().map(|l| field.div(*l, discrepancy)).collect();
+			}
+
+			lambda = next;
+		}
+
+		let degree = lambda.iter().rposition(|l| *l != 0).unwrap_or(0);
+		lambda.truncate(degree + 1);
+		lambda
+	}
+
+	fn convert_to_dual(&self, symbols: &mut [u8]) {
+		if let Some(dual_basis) = &self.dual_basis {
+			symbols.iter_mut().for_each(|symbol| *symbol = dual_basis.to_dual[*symbol as usize]);
+		}
+	}
+
+	fn convert_from_dual(&self, symbols: &mut [u8]) {
+		if let Some(dual_basis) = &self.dual_basis {
+			symbols.iter_mut().for_each(|symbol| *symbol = dual_basis.from_dual[*symbol as usize]);
+		}
+	}
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+	if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_generator() {
+		// (x + 1)(x + α)
+		assert_eq!(ReedSolomon::new(2).generator, [2, 3, 1]);
+	}
+
+	#[test]
+	fn test_qr_code() {
+		// "HELLO WORLD" as a version 1-M QR code, which uses the default field and roots
+		let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+		let codeword = ReedSolomon::new(10).encode(&data);
+
+		assert_eq!(codeword[..16], data);
+		assert_eq!(codeword[16..], [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+	}
+
+	#[test]
+	fn test_dual_basis() {
+		let dual_basis = DualBasis::new();
+
+		// the start of Taltab and Tal1tab in Phil Karn's libfec (ccsds_tab.c)
+		assert_eq!(dual_basis.to_dual[..8], [0x00, 0x7B, 0xAF, 0xD4, 0x99, 0xE2, 0x36, 0x4D]);
+		assert_eq!(dual_basis.from_dual[..4], [0x00, 0xCC, 0xAC, 0x60]);
+
+		for x in 0..=255 {
+			assert_eq!(dual_basis.from_dual[dual_basis.to_dual[x as usize] as usize], x);
+		}
+	}
+
+	/// the CCSDS RS(255, 223) generator in libfec (ccsds.h), as logs of its coefficients in the conventional
+	/// representation, x^0 first
+	const CCSDS_GENERATOR: [u8; 33] = [
+		0, 249, 59, 66, 4, 43, 126, 251, 97, 30, 3, 213, 50, 66, 170, 5, 24,
+		5, 170, 66, 50, 213, 3, 30, 97, 251, 126, 43, 4, 66, 59, 249, 0,
+	];
+
+	#[test]
+	fn test_ccsds_generator() {
+		// this depends on the field, the first root and the gap between roots all being right
+		let code = ReedSolomon::ccsds(16);
+		let logs: Vec<u8> = code.generator.iter().map(|c| code.field.log[*c as usize]).collect();
+
+		assert_eq!(logs, CCSDS_GENERATOR);
+	}
+
+	#[test]
+	fn test_ccsds_codeword() {
+		// libfec's encode_rs_ccsds, written out using its tables rather than anything above: convert the
+		// data out of the dual basis, divide by the generator with a shift register, and convert the
+		// parity back
+		let field = Field::new(0x187);
+		let dual_basis = DualBasis::new();
+
+		let data: Vec<u8> = (0..223u32).map(|x| (x * 151 % 256) as u8).collect();
+
+		let mut register = [0u8; 32];
+		for byte in &data {
+			let feedback = dual_basis.from_dual[*byte as usize] ^ register[0];
+			register.rotate_left(1);
+			register[31] = 0;
+
+			if feedback != 0 {
+				let log = field.log[feedback as usize] as usize;
+
+				for (j, r) in register.iter_mut().enumerate() {
+					*r ^= field.pow(log + CCSDS_GENERATOR[31 - j] as usize);
+				}
+			}
+		}
+
+		let parity: Vec<u8> = register.iter().map(|r| dual_basis.to_dual[*r as usize]).collect();
+
+		let code = ReedSolomon::ccsds(16);
+		let codeword = code.encode(&data);
+		assert_eq!(codeword[223..], parity);
+
+		// and it decodes, with as many errors as it can correct
+		let mut received = codeword.clone();
+		for i in 0..16 {
+			received[i * 16 + 3] ^= 0x5A;
+		}
+
+		assert_eq!(code.decode(&mut received, &[]), Ok(16));
+		assert_eq!(received, codeword);
+	}
+
+	#[test]
+	#[should_panic(expected = "primitive")]
+	fn test_field_not_primitive() {
+		// x^8 + x^4 + x^3 + x + 1 is irreducible, but x has order 51
+		ReedSolomon::new(4).with_field(0x11B);
+	}
+
+	fn codeword(code: &ReedSolomon, len: usize) -> Vec<u8> {
+		let data: Vec<u8> = (0..len - code.parity()).map(|x| (x * 37 % 256) as u8).collect();
+		code.encode(&data)
+	}
+
+	#[test]
+	fn test_corrects_errors() {
+		for code in [ReedSolomon::new(16), ReedSolomon::ccsds(16), ReedSolomon::ccsds(8)] {
+			let codeword = codeword(&code, 255);
+
+			// the most errors it can correct
+			let mut received = codeword.clone();
+			for i in 0..code.parity() / 2 {
+				received[i * 15] ^= (i + 1) as u8;
+			}
+
+			assert_eq!(code.decode(&mut received, &[]), Ok(code.parity() / 2));
+			assert_eq!(received, codeword);
+
+			// nothing to correct
+			assert_eq!(code.decode(&mut received, &[]), Ok(0));
+		}
+	}
+
+	#[test]
+	fn test_corrects_erasures() {
+		let code = ReedSolomon::ccsds(16);
+		let codeword = codeword(&code, 255);
+
+		// twice as many erasures as errors, some of which happen to be right
+		let erasures: Vec<usize> = (0..32).map(|i| i * 7).collect();
+		let mut received = codeword.clone();
+		for i in &erasures[..28] {
+			received[*i] = 0x55;
+		}
+
+		assert!(code.decode(&mut received, &erasures).is_ok());
+		assert_eq!(received, codeword);
+
+		// 20 erasures and 6 errors
+		let mut received = codeword.clone();
+		for i in 0..26 {
+			received[i * 9] ^= 0xFF;
+		}
+
+		let erasures: Vec<usize> = (0..20).map(|i| i * 9).collect();
+		assert_eq!(code.decode(&mut received, &erasures), Ok(26));
+		assert_eq!(received, codeword);
+	}
+
+	#[test]
+	fn test_too_many_errors() {
+		let code = ReedSolomon::ccsds(16);
+		let codeword = codeword(&code, 255);
+
+		let mut received = codeword.clone();
+		for i in 0..17 {
+			received[i * 13] ^= 0xA5;
+		}
+
+		let before = received.clone();
+		assert_eq!(code.decode(&mut received, &[]), Err(Uncorrectable));
+		assert_eq!(received, before);
+	}
+
+	#[test]
+	fn test_shortened() {
+		let code = ReedSolomon::ccsds(16);
+		let codeword = codeword(&code, 100);
+		assert_eq!(codeword.len(), 100);
+
+		let mut received = codeword.clone();
+		for i in 0..16 {
+			received[i * 6] ^= 0x01;
+		}
+
+		assert_eq!(code.decode(&mut received, &[]), Ok(16));
+		assert_eq!(received, codeword);
+	}
+
+	#[test]
+	fn test_interleaved_burst() {
+		let code = ReedSolomon::ccsds(16);
+		let data: Vec<u8> = (0..4 * 223).map(|x| (x * 11 % 256) as u8).collect();
+
+		let block = code.encode_interleaved(&data, 4);
+		assert_eq!(block.len(), 4 * 255);
+		assert_eq!(block[..data.len()], data);
+
+		// codeword 1 is every 4th byte from byte 1
+		let codeword: Vec<u8> = data.iter().skip(1).step_by(4).copied().collect();
+		assert_eq!(block.iter().skip(1).step_by(4).copied().collect::<Vec<_>>(), code.encode(&codeword));
+
+		// a burst of 64 bytes is 16 in each codeword, as is a burst of 128 erased bytes
+		let mut received = block.clone();
+		received[300..364].iter_mut().for_each(|byte| *byte = !*byte);
+		assert_eq!(code.decode_interleaved(&mut received, 4, &[]), Ok(64));
+		assert_eq!(received, block);
+
+		received[500..628].iter_mut().for_each(|byte| *byte = 0);
+		let erasures: Vec<usize> = (500..628).collect();
+		assert!(code.decode_interleaved(&mut received, 4, &erasures).is_ok());
+		assert_eq!(received, block);
+
+		// one more and it's too many for codeword 0, but the rest are still corrected
+		received[300..365].iter_mut().for_each(|byte| *byte = !*byte);
+		assert_eq!(code.decode_interleaved(&mut received, 4, &[]), Err(Uncorrectable));
+
+		for j in 300..365 {
+			assert_eq!(received[j] == block[j], j % 4 != 0);
+		}
+	}
+}
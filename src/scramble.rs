@@ -0,0 +1,338 @@
+//! scramblers (also called randomisers), which XOR data with a pseudo-random sequence from a linear
+//! feedback shift register before it is encoded, so that long runs of the same bit are sent as a good
+//! mix of 0s and 1s.
+//!
+//! polynomials are written with bit `i` being the coefficient of x^i, so x^7 + x^4 + 1 is `0x91`. the
+//! register holds the last `degree` bits that were fed back, bit 0 being the newest (x^1) and bit
+//! `degree - 1` the oldest (x^degree).
+//!
+//! bytes are scrambled most significant bit first, the same way the presets send them. scrambling is
+//! done on the bytes before they are pushed into an encoder, e.g.
+//! `encoder.push_slice(&scrambler.scrambled(data))`, and undone on the decoded bytes.
+
+/// the IEEE 802.11 scrambler's polynomial, x^7 + x^4 + 1
+pub(crate) const IEEE80211_POLYNOMIAL: u64 = 0x91;
+
+/// the degree of a polynomial, checking it's one a scrambler can use
+fn degree(polynomial: u64) -> u32 {
+	assert!(polynomial & 1 == 1, "the polynomial must have a constant term");
+	assert!(polynomial > 1, "the polynomial must have a degree of at least 1");
+
+	63 - polynomial.leading_zeros()
+}
+
+/// the bit fed back into a register holding `state`
+#[inline]
+fn feedback(polynomial: u64, state: u64) -> u8 {
+	((polynomial >> 1) & state).count_ones() as u8 & 1
+}
+
+/// apply `f` to each bit of `bytes`, most significant first, replacing it with what `f` returns
+fn map_bits(bytes: &mut [u8], mut f: impl FnMut(u8) -> u8) {
+	for byte in bytes {
+		*byte = (0..8).rev().fold(0, |acc, i| (acc << 1) | f((*byte >> i) & 1));
+	}
+}
+
+/// XORs the data with the sequence output by a free running LFSR. the same scrambler (started from the
+/// same state) unscrambles it again, so the receiver has to know where each frame starts.
+///
+/// bit errors stay where they are, which makes it the usual choice in front of a convolutional code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdditiveScrambler {
+	polynomial: u64,
+	seed: u64,
+	state: u64,
+}
+
+impl AdditiveScrambler {
+	/// a scrambler with the feedback `polynomial`, starting in state `seed` (which can't be 0, as the
+	/// register would stay 0 forever)
+	pub fn new(polynomial: u64, seed: u64) -> Self {
+		let degree = degree(polynomial);
+		assert!(seed != 0 && seed >> degree == 0, "the seed must fit in the register and not be 0");
+
+		Self {
+			polynomial,
+			seed,
+			state: seed,
+		}
+	}
+
+	/// a scrambler that is already in `state`, which unlike a seed may be 0 (e.g. when the state was
+	/// worked out from received bits that were wrong). it resets to the same state
+	pub(crate) fn from_state(polynomial: u64, state: u64) -> Self {
+		assert!(state >> degree(polynomial) == 0, "the state must fit in the register");
+
+		Self {
+			polynomial,
+			seed: state,
+			state,
+		}
+	}
+
+	/// IEEE 802.11, S(x) = x^7 + x^4 + 1, starting in `seed` (7 bits)
+	pub fn ieee80211(seed: u8) -> Self {
+		Self::new(IEEE80211_POLYNOMIAL, seed as u64)
+	}
+
+	/// the DVB energy dispersal sequence (EN 300 421), 1 + x^14 + x^15, loaded with 100101010000000.
+	///
+	/// DVB also inverts the sync byte at the start of every 8 packets and restarts the sequence there,
+	/// which is left to the caller
+	pub fn dvb() -> Self {
+		Self::new(1 << 15 | 1 << 14 | 1, 0b000000010101001)
+	}
+
+	/// the CCSDS pseudo-randomiser (CCSDS 131.0-B), whose sequence starts with 8 1s and repeats every
+	/// 255 bits. the standard gives it as h(x) = x^8 + x^7 + x^5 + x^3 + 1, counting the taps from the
+	/// other end of the register, which is x^8 + x^5 + x^3 + x + 1 here
+	pub fn ccsds() -> Self {
+		let mut scrambler = Self::new(0x12B, 0xFF);
+
+		// the standard outputs the register's contents first, so start where they are still to come
+		scrambler.rewind(8);
+		scrambler.seed = scrambler.state;
+		scrambler
+	}
+
+	/// the number of bits in the register
+	pub fn degree(&self) -> u32 {
+		degree(self.polynomial)
+	}
+
+	pub fn state(&self) -> u64 {
+		self.state
+	}
+
+	/// the next bit of the sequence
+	pub fn next_bit(&mut self) -> u8 {
+		let bit = feedback(self.polynomial, self.state);
+		let mask = (1 << self.degree()) - 1;
+
+		self.state = ((self.state << 1) | bit as u64) & mask;
+		bit
+	}
+
+	/// undo `count` steps, recovering the state the scrambler was in before them
+	pub fn rewind(&mut self, count: usize) {
+		let top = self.degree() - 1;
+
+		for _ in 0..count {
+			// the newest bit was fed back from the oldest one (which has since been shifted out) and the
+			// others, which have all moved up one
+			let others = (self.polynomial >> 1) & !(1 << top) & (self.state >> 1);
+			let oldest = (self.state & 1) ^ (others.count_ones() as u64 & 1);
+
+			self.state = (self.state >> 1) | (oldest << top);
+		}
+	}
+
+	/// go back to the seed, e.g. at the start of a frame
+	pub fn reset(&mut self) {
+		self.state = self.seed;
+	}
+
+	/// scramble (or unscramble) bits, each 0 or 1
+	pub fn scramble_bits(&mut self, bits: &mut [u8]) {
+		for bit in bits {
+			*bit ^= self.next_bit();
+		}
+	}
+
+	/// scramble (or unscramble) bytes, most significant bit first
+	pub fn scramble(&mut self, bytes: &mut [u8]) {
+		map_bits(bytes, |bit| bit ^ self.next_bit());
+	}
+
+	/// the same as [`AdditiveScrambler::scramble`], but returning a scrambled copy
+	pub fn scrambled(&mut self, bytes: &[u8]) -> Vec<u8> {
+		let mut bytes = bytes.to_vec();
+		self.scramble(&mut bytes);
+		bytes
+	}
+}
+
+/// feeds the scrambled bits themselves back into the register, so the descrambler's register fills with
+/// the same bits and it synchronises by itself after `degree` bits, wherever it starts.
+///
+/// the price is that each bit error is multiplied by the number of terms in the polynomial, which makes
+/// bursts of errors for the decoder (or makes more errors after it, if descrambling the decoded data)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiplicativeScrambler {
+	polynomial: u64,
+	state: u64,
+}
+
+impl MultiplicativeScrambler {
+	/// a scrambler with the feedback `polynomial`, with the register starting as 0s
+	pub fn new(polynomial: u64) -> Self {
+		degree(polynomial);
+
+		Self {
+			polynomial,
+			state: 0,
+		}
+	}
+
+	/// the ITU-T V.34 scrambler, 1 + x^-18 + x^-23
+	pub fn v34() -> Self {
+		Self::new(1 << 23 | 1 << 18 | 1)
+	}
+
+	/// the 64b/66b scrambler of 10 gigabit ethernet (IEEE 802.3 clause 49), 1 + x^39 + x^58
+	pub fn ethernet() -> Self {
+		Self::new(1 << 58 | 1 << 39 | 1)
+	}
+
+	/// the self-synchronising scrambler for ATM cells over SONET/SDH, x^43 + 1
+	pub fn sonet() -> Self {
+		Self::new(1 << 43 | 1)
+	}
+
+	/// the number of bits in the register, which is how many bits it takes to synchronise
+	pub fn degree(&self) -> u32 {
+		degree(self.polynomial)
+	}
+
+	/// clear the register
+	pub fn reset(&mut self) {
+		self.state = 0;
+	}
+
+	fn push(&mut self, scrambled: u8) {
+		let mask = (1u64 << self.degree()) - 1;
+		self.state = ((self.state << 1) | scrambled as u64) & mask;
+	}
+
+	/// scramble one bit (0 or 1)
+	pub fn scramble_bit(&mut self, bit: u8) -> u8 {
+		let scrambled = bit ^ feedback(self.polynomial, self.state);
+		self.push(scrambled);
+
+		scrambled
+	}
+
+	/// descramble one bit (0 or 1)
+	pub fn descramble_bit(&mut self, scrambled: u8) -> u8 {
+		let bit = scrambled ^ feedback(self.polynomial, self.state);
+		self.push(scrambled);
+
+		bit
+	}
+
+	/// scramble bytes, most significant bit first
+	pub fn scramble(&mut self, bytes: &mut [u8]) {
+		map_bits(bytes, |bit| self.scramble_bit(bit));
+	}
+
+	/// descramble bytes, most significant bit first. the first `degree` bits are wrong unless the register
+	/// started the same as the scrambler's
+	pub fn descramble(&mut self, bytes: &mut [u8]) {
+		map_bits(bytes, |bit| self.descramble_bit(bit));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::unpack_bits;
+
+	#[test]
+	fn test_ccsds_sequence() {
+		// from CCSDS 131.0-B
+		let mut scrambler = AdditiveScrambler::ccsds();
+		assert_eq!(scrambler.scrambled(&[0; 8]), [0xFF, 0x48, 0x0E, 0xC0, 0x9A, 0x0D, 0x70, 0xBC]);
+
+		// it repeats every 255 bits
+		let sequence: Vec<u8> = (0..255 * 2).map(|_| scrambler.next_bit()).collect();
+		assert_eq!(sequence[..255], sequence[255..]);
+
+		scrambler.reset();
+		assert_eq!(scrambler.scrambled(&[0]), [0xFF]);
+	}
+
+	#[test]
+	fn test_dvb_sequence() {
+		// the first bytes of the sequence, as XORed with the first packet after its sync byte
+		let mut scrambler = AdditiveScrambler::dvb();
+		assert_eq!(scrambler.scrambled(&[0; 4]), [0x03, 0xF6, 0x08, 0x34]);
+	}
+
+	#[test]
+	fn test_ieee80211_matches_preset() {
+		let mut scrambler = AdditiveScrambler::ieee80211(0x5D);
+		let mut preset = crate::presets::ieee80211::Scrambler::new(0x5D);
+
+		for _ in 0..200 {
+			assert_eq!(scrambler.next_bit(), preset.next_bit());
+		}
+	}
+
+	#[test]
+	fn test_rewind() {
+		let mut scrambler = AdditiveScrambler::dvb();
+
+		for _ in 0..1000 {
+			scrambler.next_bit();
+		}
+
+		scrambler.rewind(1000);
+		assert_eq!(scrambler, AdditiveScrambler::dvb());
+	}
+
+	#[test]
+	fn test_additive_round_trip() {
+		let data: Vec<u8> = (0..100).map(|x| x * 2).collect();
+
+		let scrambled = AdditiveScrambler::ieee80211(0x7F).scrambled(&data);
+		assert_ne!(scrambled, data);
+		assert_eq!(AdditiveScrambler::ieee80211(0x7F).scrambled(&scrambled), data);
+	}
+
+	#[test]
+	fn test_multiplicative_self_synchronises() {
+		let data: Vec<u8> = (0..100).map(|x| (x * 31 % 256) as u8).collect();
+
+		let mut scrambled = data.clone();
+		let mut scrambler = MultiplicativeScrambler::v34();
+		scrambler.scramble(&mut scrambled);
+
+		// zeros don't stay zeros
+		let mut zeros = [0; 8];
+		scrambler.scramble(&mut zeros);
+		assert!(zeros.iter().any(|byte| *byte != 0));
+
+		// starting from the same state it all comes back
+		let mut descrambled = scrambled.clone();
+		MultiplicativeScrambler::v34().descramble(&mut descrambled);
+		assert_eq!(descrambled, data);
+
+		// joining part way through, it takes 23 bits (so 3 bytes) to synchronise
+		let mut descrambled = scrambled[10..].to_vec();
+		MultiplicativeScrambler::v34().descramble(&mut descrambled);
+		assert_ne!(descrambled[..3], data[10..13]);
+		assert_eq!(descrambled[3..], data[13..]);
+	}
+
+	#[test]
+	fn test_multiplicative_error_multiplication() {
+		let data = [0u8; 16];
+
+		let mut scrambled = data;
+		MultiplicativeScrambler::v34().scramble(&mut scrambled);
+		scrambled[4] ^= 0x80;
+
+		let mut descrambled = scrambled;
+		MultiplicativeScrambler::v34().descramble(&mut descrambled);
+
+		// the error, then again 18 and 23 bits later
+		let errors: Vec<usize> = unpack_bits(&descrambled).iter()
+			.enumerate()
+			.filter(|(_, bit)| **bit != 0)
+			.map(|(i, _)| i)
+			.collect();
+
+		assert_eq!(errors, [32, 50, 55]);
+	}
+}
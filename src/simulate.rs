@@ -0,0 +1,317 @@
+//! monte carlo simulation of encoding, sending through an [`Awgn`] channel and decoding, for measuring
+//! the bit and frame error rates of a code and decoder at different Eb/N0.
+//!
+//! the data is sent the way [`EncoderState<u8>`] sends it, so every packet is 8 frames side by side (one
+//! per bit of each byte). each packet ends with K - 1 bytes of 0s to bring the encoders back to state 0,
+//! which is counted against the rate when working out Eb/N0. packets are run in parallel, each with its
+//! own seed, so the results are the same however many threads there are.
+
+use std::fmt::Write;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+
+use crate::channel::{Awgn, Channel};
+use crate::code::Code;
+use crate::decode::Decoder;
+use crate::encode::EncoderState;
+
+/// how many packets are run between checks of whether to stop
+const BATCH: usize = 64;
+
+/// for 95% confidence intervals
+const Z: f64 = 1.96;
+
+/// the error rates measured at one Eb/N0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+	/// in dB
+	pub eb_n0: f64,
+	pub bits: u64,
+	pub bit_errors: u64,
+	pub frames: u64,
+	pub frame_errors: u64,
+}
+
+impl Point {
+	pub fn ber(&self) -> f64 {
+		self.bit_errors as f64 / self.bits as f64
+	}
+
+	pub fn fer(&self) -> f64 {
+		self.frame_errors as f64 / self.frames as f64
+	}
+
+	/// the 95% confidence interval of the bit error rate, as (low, high).
+	///
+	/// this treats every bit as independent, which they aren't (errors come in bursts after decoding),
+	/// so it's narrower than it should be
+	pub fn ber_interval(&self) -> (f64, f64) {
+		wilson_interval(self.bit_errors, self.bits)
+	}
+
+	/// the 95% confidence interval of the frame error rate, as (low, high)
+	pub fn fer_interval(&self) -> (f64, f64) {
+		wilson_interval(self.frame_errors, self.frames)
+	}
+}
+
+/// the wilson score interval for `successes` out of `trials`, which (unlike the normal approximation)
+/// still makes sense when there are very few or none
+pub fn wilson_interval(successes: u64, trials: u64) -> (f64, f64) {
+	assert!(trials > 0);
+
+	let n = trials as f64;
+	let p = successes as f64 / n;
+	let z2 = Z * Z;
+
+	let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+	let half = Z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+
+	((center - half).max(0.0), (center + half).min(1.0))
+}
+
+/// write the points as CSV, with a header row
+pub fn to_csv(points: &[Point]) -> String {
+	let mut csv = String::from("eb_n0,bits,bit_errors,ber,ber_low,ber_high,frames,frame_errors,fer,fer_low,fer_high\n");
+
+	for point in points {
+		let (ber_low, ber_high) = point.ber_interval();
+		let (fer_low, fer_high) = point.fer_interval();
+
+		writeln!(
+			csv,
+			"{},{},{},{:e},{:e},{:e},{},{},{:e},{:e},{:e}",
+			point.eb_n0,
+			point.bits, point.bit_errors, point.ber(), ber_low, ber_high,
+			point.frames, point.frame_errors, point.fer(), fer_low, fer_high,
+		).unwrap();
+	}
+
+	csv
+}
+
+/// the settings for a simulation
+#[derive(Debug, Clone)]
+pub struct Simulation {
+	code: Code,
+	frame_len: usize,
+	target_errors: u64,
+	max_frames: u64,
+	hard: bool,
+	seed: u64,
+}
+
+impl Simulation {
+	/// simulate `code` with frames of `frame_len` bits (so packets of `frame_len` bytes)
+	pub fn new(code: &Code, frame_len: usize) -> Self {
+		assert!(frame_len > 0);
+
+		Self {
+			code: code.clone(),
+			frame_len,
+			target_errors: 100,
+			max_frames: 1_000_000,
+			hard: false,
+			seed: 0,
+		}
+	}
+
+	/// stop once this many bit errors have been seen (the default is 100). more gives tighter confidence intervals
+	pub fn with_target_errors(mut self, target_errors: u64) -> Self {
+		assert!(target_errors > 0);
+
+		self.target_errors = target_errors;
+		self
+	}
+
+	/// stop after this many frames even if not enough errors have been seen (the default is 1,000,000).
+	/// this is checked after every 512 frames, so a few more may be run
+	pub fn with_max_frames(mut self, max_frames: u64) -> Self {
+		self.max_frames = max_frames;
+		self
+	}
+
+	/// make hard decisions on the received symbols instead of passing soft symbols to the decoder
+	pub fn with_hard_decisions(mut self) -> Self {
+		self.hard = true;
+		self
+	}
+
+	pub fn with_seed(mut self, seed: u64) -> Self {
+		self.seed = seed;
+		self
+	}
+
+	/// the rate of the code including the tail, which Eb/N0 is worked out with
+	pub fn rate(&self) -> f64 {
+		let tail = self.code.constraint_len() as usize - 1;
+
+		self.frame_len as f64 / ((self.frame_len + tail) * self.code.outputs()) as f64
+	}
+
+	/// run the simulation at each Eb/N0 (in dB) in turn.
+	///
+	/// `new_decoder` makes a decoder for the code and packet length (in bytes, including the tail), e.g.
+	/// [`DecoderState::with_code`](crate::prelude::DecoderState::with_code). rayon makes one for each piece of work it
+	/// splits a batch of packets into, and reuses it for every packet in that piece
+	pub fn sweep<D, F>(&self, eb_n0s: &[f64], new_decoder: F) -> Vec<Point>
+	where
+		D: Decoder,
+		F: Fn(&Code, usize) -> D + Sync + Send,
+	{
+		eb_n0s.iter()
+			.map(|eb_n0| self.run(*eb_n0, &new_decoder))
+			.collect()
+	}
+
+	/// run the simulation at one Eb/N0 (in dB), see [`Simulation::sweep`]
+	pub fn run<D, F>(&self, eb_n0: f64, new_decoder: F) -> Point
+	where
+		D: Decoder,
+		F: Fn(&Code, usize) -> D + Sync + Send,
+	{
+		let packet_len = self.frame_len + self.code.constraint_len() as usize - 1;
+
+		let mut point = Point {
+			eb_n0,
+			bits: 0,
+			bit_errors: 0,
+			frames: 0,
+			frame_errors: 0,
+		};
+
+		let mut packet = 0;
+
+		while point.bit_errors < self.target_errors && point.frames < self.max_frames {
+			let (bit_errors, frame_errors) = (packet..packet + BATCH as u64)
+				.into_par_iter()
+				.map_init(
+					|| (new_decoder(&self.code, packet_len), vec![0; packet_len]),
+					|(decoder, output), packet| self.run_packet(eb_n0, packet, decoder, output),
+				)
+				.reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+			packet += BATCH as u64;
+
+			point.bits += (BATCH * self.frame_len * 8) as u64;
+			point.bit_errors += bit_errors;
+			point.frames += BATCH as u64 * 8;
+			point.frame_errors += frame_errors;
+		}
+
+		point
+	}
+
+	/// send one packet, returning the number of bit errors and frame errors
+	fn run_packet(&self, eb_n0: f64, packet: u64, decoder: &mut impl Decoder, output: &mut [u8]) -> (u64, u64) {
+		let seed = self.seed
+			.wrapping_add(packet)
+			.wrapping_mul(0x9E3779B97F4A7C15)
+			^ eb_n0.to_bits();
+
+		let mut rng = StdRng::seed_from_u64(seed);
+		let mut data = vec![0; output.len()];
+		rng.fill_bytes(&mut data[..self.frame_len]);
+
+		let encoded = EncoderState::new(self.code.clone()).push_slice(&data);
+		let mut channel = Awgn::new(eb_n0, self.rate(), rng.gen());
+
+		// the tail bytes bring every encoder back to state 0
+		decoder.reset();
+		decoder.set_terminated(true);
+
+		if self.hard {
+			decoder.push_slice(&channel.transmit(&encoded));
+		} else {
+			decoder.push_soft_slice(&channel.transmit_soft(&encoded));
+		}
+
+		decoder.read_into(output);
+
+		// each bit that is wrong in any byte is a frame in error
+		let (bit_errors, wrong_frames) = data[..self.frame_len].iter()
+			.zip(&output[..self.frame_len])
+			.fold((0, 0u8), |(bits, frames), (a, b)| (bits + (a ^ b).count_ones(), frames | (a ^ b)));
+
+		(bit_errors as u64, wrong_frames.count_ones() as u64)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::decode::{DecoderState, RcDecoderState};
+
+	#[test]
+	fn test_wilson_interval() {
+		let (low, high) = wilson_interval(10, 100);
+		assert!((low - 0.0552).abs() < 1e-4, "{low}");
+		assert!((high - 0.1744).abs() < 1e-4, "{high}");
+
+		// no errors still gives an upper bound
+		let (low, high) = wilson_interval(0, 1000);
+		assert_eq!(low, 0.0);
+		assert!((high - 0.00383).abs() < 1e-5, "{high}");
+	}
+
+	#[test]
+	fn test_stops_at_target_errors() {
+		let simulation = Simulation::new(&Code::default(), 32).with_target_errors(50);
+		let point = simulation.run(0.0, DecoderState::with_code);
+
+		assert!(point.bit_errors >= 50);
+
+		// only one batch was needed
+		assert_eq!(point.frames, BATCH as u64 * 8);
+		assert!(point.frame_errors > 0 && point.frame_errors <= point.frames);
+	}
+
+	#[test]
+	fn test_stops_at_max_frames() {
+		let simulation = Simulation::new(&Code::ccsds(), 32).with_max_frames(1000);
+		let point = simulation.run(12.0, |code, _| RcDecoderState::with_code(code));
+
+		assert_eq!(point.bit_errors, 0);
+		assert_eq!(point.frames, 1024);
+		assert_eq!(point.bits, 1024 * 32);
+	}
+
+	#[test]
+	fn test_deterministic() {
+		let simulation = Simulation::new(&Code::default(), 16).with_hard_decisions().with_seed(4);
+		let new_decoder = DecoderState::with_code;
+
+		assert_eq!(simulation.run(2.0, new_decoder), simulation.run(2.0, new_decoder));
+	}
+
+	#[test]
+	fn test_soft_beats_hard() {
+		let simulation = Simulation::new(&Code::default(), 32).with_target_errors(200);
+		let new_decoder = DecoderState::with_code;
+
+		let soft = simulation.run(3.0, new_decoder);
+		let hard = simulation.clone().with_hard_decisions().run(3.0, new_decoder);
+
+		assert!(soft.ber() < hard.ber(), "{} {}", soft.ber(), hard.ber());
+	}
+
+	#[test]
+	fn test_csv() {
+		let points = [Point {
+			eb_n0: 1.5,
+			bits: 1000,
+			bit_errors: 10,
+			frames: 100,
+			frame_errors: 0,
+		}];
+
+		let csv = to_csv(&points);
+		let mut lines = csv.lines();
+
+		assert_eq!(lines.next().unwrap().split(',').count(), 11);
+		assert!(lines.next().unwrap().starts_with("1.5,1000,10,1e-2,"));
+		assert_eq!(lines.next(), None);
+	}
+}